@@ -1,3 +1,7 @@
+use rand::Rng;
+
+use super::random;
+use super::spectrum::Wavelength;
 use super::vector::{Ray, Vec3};
 
 pub struct Camera {
@@ -9,9 +13,13 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    // Times the shutter opens and closes, which rays are spread evenly between
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -20,6 +28,8 @@ impl Camera {
         aspect_ratio: f64,
         aperture: f64,
         focus_distance: f64,
+        time0: f64,
+        time1: f64,
     ) -> Camera {
         // Camera Properties
 
@@ -46,16 +56,28 @@ impl Camera {
             v,
             w,
             lens_radius: aperture / 2.0,
+            time0,
+            time1,
         }
     }
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+    pub fn get_ray(&self, s: f64, t: f64, wavelength: Wavelength) -> Ray {
         let rd = self.lens_radius * Vec3::random_unit_disk();
         let offset = self.u * rd.x() + self.v * rd.y();
+
+        // An instantaneous shutter leaves the random sequence as it was without motion blur
+        let time = if self.time1 > self.time0 {
+            random::rng().gen_range(self.time0, self.time1)
+        } else {
+            self.time0
+        };
+
         Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + (s * self.horizontal) + (t * self.vertical)
                 - self.origin
                 - offset,
+            wavelength,
+            time,
         }
     }
 }
@@ -0,0 +1,186 @@
+use rayon::prelude::*;
+
+use super::renderer::Sample;
+use super::vector::Vec3;
+
+// Keeps the albedo demodulation from blowing up on black surfaces
+const ALBEDO_EPSILON: f64 = 0.001;
+
+// Joint bilateral filter guided by the first-hit albedo and normal buffers.
+//
+// The radiance is divided by the albedo before filtering so texture detail survives, then
+// multiplied back afterwards. Neighbours only contribute when they are close in screen space,
+// color, albedo and normal.
+pub struct JointBilateral {
+    pub radius: usize,
+    pub sigma_spatial: f64,
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+}
+
+impl Default for JointBilateral {
+    fn default() -> Self {
+        JointBilateral {
+            radius: 5,
+            sigma_spatial: 3.0,
+            sigma_color: 0.5,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.2,
+        }
+    }
+}
+
+fn demodulate(sample: &Sample) -> Vec3 {
+    Vec3(
+        sample.color.x() / sample.albedo.x().max(ALBEDO_EPSILON),
+        sample.color.y() / sample.albedo.y().max(ALBEDO_EPSILON),
+        sample.color.z() / sample.albedo.z().max(ALBEDO_EPSILON),
+    )
+}
+
+fn gaussian(distance_sqr: f64, sigma: f64) -> f64 {
+    (-distance_sqr / (2.0 * sigma * sigma)).exp()
+}
+
+impl JointBilateral {
+    // Filters a float framebuffer, returning the denoised radiance in the same layout
+    pub fn denoise(&self, frame: &[Vec<Sample>]) -> Vec<Vec<Vec3>> {
+        let height = frame.len();
+        let irradiance: Vec<Vec<Vec3>> = frame
+            .iter()
+            .map(|line| line.iter().map(demodulate).collect())
+            .collect();
+
+        (0..height)
+            .into_par_iter()
+            .map(|y| {
+                let width = frame[y].len();
+                (0..width)
+                    .map(|x| self.filter_pixel(frame, &irradiance, x, y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn filter_pixel(
+        &self,
+        frame: &[Vec<Sample>],
+        irradiance: &[Vec<Vec3>],
+        x: usize,
+        y: usize,
+    ) -> Vec3 {
+        let center = &frame[y][x];
+        let center_irradiance = irradiance[y][x];
+
+        let y_range = y.saturating_sub(self.radius)..(y + self.radius + 1).min(frame.len());
+
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        let mut weight_sum = 0.0;
+
+        for j in y_range {
+            let x_range = x.saturating_sub(self.radius)..(x + self.radius + 1).min(frame[j].len());
+            for i in x_range {
+                let neighbour = &frame[j][i];
+                let dx = i as f64 - x as f64;
+                let dy = j as f64 - y as f64;
+
+                let weight = gaussian(dx * dx + dy * dy, self.sigma_spatial)
+                    * gaussian(
+                        (irradiance[j][i] - center_irradiance).len_sqr(),
+                        self.sigma_color,
+                    )
+                    * gaussian(
+                        (neighbour.albedo - center.albedo).len_sqr(),
+                        self.sigma_albedo,
+                    )
+                    * gaussian(
+                        (neighbour.normal - center.normal).len_sqr(),
+                        self.sigma_normal,
+                    );
+
+                sum = sum + weight * irradiance[j][i];
+                weight_sum += weight;
+            }
+        }
+
+        // The center pixel always has weight 1, so weight_sum is never zero
+        (sum / weight_sum)
+            * Vec3(
+                center.albedo.x().max(ALBEDO_EPSILON),
+                center.albedo.y().max(ALBEDO_EPSILON),
+                center.albedo.z().max(ALBEDO_EPSILON),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+    use rand::Rng;
+
+    const SIZE: usize = 32;
+
+    fn frame<F: FnMut(usize, usize) -> Sample>(mut f: F) -> Vec<Vec<Sample>> {
+        (0..SIZE)
+            .map(|y| (0..SIZE).map(|x| f(x, y)).collect())
+            .collect()
+    }
+
+    fn variance(values: &[f64]) -> f64 {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn test_flat_noise_smoothed() {
+        random::reseed(1);
+        let mut rng = random::rng();
+        let noisy = frame(|_, _| Sample {
+            color: 0.5 * rng.gen_range(0.8, 1.2) * Vec3(1.0, 1.0, 1.0),
+            albedo: Vec3(0.5, 0.5, 0.5),
+            normal: Vec3(0.0, 0.0, 1.0),
+        });
+        let denoised = JointBilateral::default().denoise(&noisy);
+
+        let before: Vec<f64> = noisy.iter().flatten().map(|s| s.color.x()).collect();
+        let after: Vec<f64> = denoised.iter().flatten().map(|c| c.x()).collect();
+        assert!(variance(&after) < 0.1 * variance(&before));
+    }
+
+    #[test]
+    fn test_edges_kept() {
+        // Albedo changes halfway across, under even light
+        let albedo_edge = frame(|x, _| {
+            let albedo = if x < SIZE / 2 { 0.9 } else { 0.1 };
+            Sample {
+                color: Vec3(albedo, albedo, albedo),
+                albedo: Vec3(albedo, albedo, albedo),
+                normal: Vec3(0.0, 0.0, 1.0),
+            }
+        });
+        // A white surface turning away from the light
+        let normal_edge = frame(|x, _| {
+            let (color, normal) = if x < SIZE / 2 {
+                (1.0, Vec3(0.0, 0.0, 1.0))
+            } else {
+                (0.2, Vec3(1.0, 0.0, 0.0))
+            };
+            Sample {
+                color: Vec3(color, color, color),
+                albedo: Vec3(1.0, 1.0, 1.0),
+                normal,
+            }
+        });
+
+        for noisy in [albedo_edge, normal_edge].iter() {
+            let denoised = JointBilateral::default().denoise(noisy);
+            for (line, denoised) in noisy.iter().zip(denoised.iter()) {
+                for (sample, color) in line.iter().zip(denoised.iter()) {
+                    assert!((sample.color - *color).len() < 1e-3);
+                }
+            }
+        }
+    }
+}
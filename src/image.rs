@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use super::vector::Vec3;
+
+// Decoded image, top row first
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Loads an 8-bit PNG. Color maps are stored in sRGB and need converting to linear before
+// they're multiplied with radiance; data maps (normals, roughness) should be read as-is.
+pub fn load_png(path: &Path, srgb: bool) -> io::Result<Image> {
+    read_png(&mut File::open(path)?, srgb)
+}
+
+pub fn read_png<R: Read>(reader: &mut R, srgb: bool) -> io::Result<Image> {
+    // The default transformations expand palettes and strip 16-bit channels down to 8 bits
+    let decoder = png::Decoder::new(reader);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => return Err(invalid_data("unexpanded indexed PNG")),
+    };
+
+    let convert = |value: u8| {
+        let value = value as f64 / 255.0;
+        if srgb {
+            srgb_to_linear(value)
+        } else {
+            value
+        }
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut pixels = Vec::with_capacity(width * height);
+
+    for line in buffer.chunks(info.line_size).take(height) {
+        for texel in line.chunks(channels).take(width) {
+            // Alpha is ignored, grayscale gets splatted to all channels
+            pixels.push(if channels < 3 {
+                let value = convert(texel[0]);
+                Vec3(value, value, value)
+            } else {
+                Vec3(convert(texel[0]), convert(texel[1]), convert(texel[2]))
+            });
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn rgbe_to_vec(rgbe: [u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3(0.0, 0.0, 0.0);
+    }
+
+    let scale = 2.0f64.powi(rgbe[3] as i32 - 136);
+    Vec3(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+// Reads one scanline in the new run length encoding, where each channel is stored separately
+fn read_rle_scanline<R: Read>(reader: &mut R, width: usize) -> io::Result<Vec<[u8; 4]>> {
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let count = count[0] as usize;
+
+            if count > 128 {
+                // A run of one repeated value
+                let count = count - 128;
+                if x + count > width {
+                    return Err(invalid_data("HDR run overflows scanline"));
+                }
+
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for texel in &mut scanline[x..x + count] {
+                    texel[channel] = value[0];
+                }
+                x += count;
+            } else {
+                // A dump of literal values
+                if count == 0 || x + count > width {
+                    return Err(invalid_data("bad HDR dump length"));
+                }
+
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (texel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    texel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(scanline)
+}
+
+// Loads a Radiance RGBE (.hdr) image, which is already linear
+pub fn load_hdr(path: &Path) -> io::Result<Image> {
+    read_hdr(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn read_hdr<R: BufRead>(reader: &mut R) -> io::Result<Image> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("missing HDR signature"));
+    }
+
+    // Header variables end at the first blank line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("truncated HDR header"));
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line.trim() != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported HDR pixel format"));
+        }
+    }
+
+    // Only the standard top-to-bottom, left-to-right orientation is supported
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height
+                .parse::<usize>()
+                .map_err(|_| invalid_data("bad HDR height"))?,
+            width
+                .parse::<usize>()
+                .map_err(|_| invalid_data("bad HDR width"))?,
+        ),
+        _ => return Err(invalid_data("unsupported HDR orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid_data("empty HDR image"));
+    }
+    width
+        .checked_mul(height)
+        .ok_or_else(|| invalid_data("HDR image too large"))?;
+
+    // Grown as the pixels arrive rather than sized from the header, like voxel grids
+    let mut pixels = Vec::new();
+    for _ in 0..height {
+        let mut start = [0u8; 4];
+        reader.read_exact(&mut start)?;
+
+        let rle = (8..32768).contains(&width)
+            && start[0] == 2
+            && start[1] == 2
+            && ((start[2] as usize) << 8 | start[3] as usize) == width;
+
+        if rle {
+            let scanline = read_rle_scanline(reader, width)?;
+            pixels.extend(scanline.into_iter().map(rgbe_to_vec));
+        } else {
+            // Flat scanline, the four bytes we peeked at are the first pixel
+            pixels.push(rgbe_to_vec(start));
+            for _ in 1..width {
+                let mut rgbe = [0u8; 4];
+                reader.read_exact(&mut rgbe)?;
+                pixels.push(rgbe_to_vec(rgbe));
+            }
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    #[test]
+    fn test_read_png() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::RGB);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 51, 0, 255, 0]).unwrap();
+        }
+
+        let image = read_png(&mut bytes.as_slice(), false).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels[0], Vec3(1.0, 0.0, 0.2));
+        assert_eq!(image.pixels[1], Vec3(0.0, 1.0, 0.0));
+
+        let image = read_png(&mut bytes.as_slice(), true).unwrap();
+        assert!((image.pixels[0].z() - srgb_to_linear(0.2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_read_hdr_flat() {
+        let bytes = hdr("-Y 1 +X 2", &[128, 64, 0, 129, 0, 0, 0, 0]);
+
+        let image = read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels[0], rgbe_to_vec([128, 64, 0, 129]));
+        assert_eq!(image.pixels[1], Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_read_hdr_rle() {
+        // Eight pixels, each channel a dump of two values then a run of six
+        let mut pixels = vec![2, 2, 0, 8];
+        for channel in [[10, 20, 30], [40, 50, 60], [70, 80, 90], [128, 129, 130]].iter() {
+            pixels.extend_from_slice(&[2, channel[0], channel[1], 128 + 6, channel[2]]);
+        }
+        let bytes = hdr("-Y 1 +X 8", &pixels);
+
+        let image = read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_eq!(image.pixels.len(), 8);
+        assert_eq!(image.pixels[0], rgbe_to_vec([10, 40, 70, 128]));
+        assert_eq!(image.pixels[1], rgbe_to_vec([20, 50, 80, 129]));
+        for pixel in &image.pixels[2..] {
+            assert_eq!(*pixel, rgbe_to_vec([30, 60, 90, 130]));
+        }
+
+        // A run past the end of the scanline
+        pixels[7] = 128 + 7;
+        let bytes = hdr("-Y 1 +X 8", &pixels);
+        assert!(read_hdr(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_read_hdr_bad_resolution() {
+        for resolution in ["-Y 4000000000 +X 4000000000", "-Y 2 +X 0", "+Y 1 +X 1"].iter() {
+            let bytes = hdr(resolution, &[0; 8]);
+            assert!(read_hdr(&mut bytes.as_slice()).is_err(), "{}", resolution);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::vector::Vec3;
+
+// Light arriving at a point from a light source, ignoring anything in the way
+pub struct LightSample {
+    // Unit vector from the point towards the light
+    pub direction: Vec3,
+    // Distance to the light, infinite for lights that are infinitely far away
+    pub distance: f64,
+    // Irradiance on a surface facing the light
+    pub irradiance: Vec3,
+}
+
+// Lights that can't be hit by rays, only sampled with shadow rays. Each one sits at a single point
+// or shines from a single direction, so sampling is exact.
+pub trait Light: Send + Sync {
+    fn sample(&self, point: Vec3) -> Option<LightSample>;
+
+    // Fill lights are often nicer without shadows
+    fn casts_shadows(&self) -> bool;
+}
+
+pub struct PointLight {
+    pub position: Vec3,
+    // Radiant intensity, the power per unit of solid angle in every direction
+    pub intensity: Vec3,
+    pub casts_shadows: bool,
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance = offset.len();
+
+        Some(LightSample {
+            direction: offset / distance,
+            distance,
+            irradiance: self.intensity / (distance * distance),
+        })
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
+// Point light restricted to a cone. Full intensity within the inner angle, fading out smoothly to
+// nothing at the outer angle. Angles are in degrees from the center of the cone.
+pub struct SpotLight {
+    pub position: Vec3,
+    // Direction the light shines towards
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub casts_shadows: bool,
+}
+
+impl SpotLight {
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+
+        if cos_theta >= cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= cos_outer || cos_inner <= cos_outer {
+            return 0.0;
+        }
+
+        let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance = offset.len();
+        let direction = offset / distance;
+
+        let falloff = self.falloff(Vec3::dot(-direction, self.direction.unit()));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: (falloff / (distance * distance)) * self.intensity,
+        })
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
+// Light from infinitely far away, like the sun
+pub struct DirectionalLight {
+    // Direction the light travels in
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+    pub casts_shadows: bool,
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction.unit(),
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spot_light_falloff() {
+        let spot = SpotLight {
+            position: Vec3(0.0, 0.0, 0.0),
+            direction: Vec3(0.0, -1.0, 0.0),
+            intensity: Vec3(1.0, 1.0, 1.0),
+            inner_angle: 10.0,
+            outer_angle: 20.0,
+            casts_shadows: true,
+        };
+        let falloff = |degrees: f64| spot.falloff(degrees.to_radians().cos());
+
+        assert_eq!(falloff(0.0), 1.0);
+        assert_eq!(falloff(9.0), 1.0);
+        assert_eq!(falloff(21.0), 0.0);
+        assert!(spot.sample(Vec3(0.0, 1.0, 0.0)).is_none());
+
+        // Falls steadily between the cones, without jumps at either edge
+        assert!(1.0 - falloff(10.01) < 1e-3);
+        assert!(falloff(19.99) < 1e-3);
+        let mut previous = 1.0;
+        for step in 1..10 {
+            let value = falloff(10.0 + step as f64);
+            assert!(value < previous && value > 0.0);
+            previous = value;
+        }
+    }
+}
@@ -2,10 +2,22 @@
 
 mod bvh;
 mod camera;
+mod denoise;
+mod image;
+mod light;
 mod material;
+mod medium;
+mod microfacet;
 mod models;
+mod perlin;
+mod random;
 mod renderer;
+mod sky;
+mod spectrum;
+mod texture;
+mod thin_film;
 mod vector;
+mod voxel;
 
 use std::fs::File;
 use std::io::BufWriter;
@@ -16,14 +28,28 @@ use rand::Rng;
 
 use crate::bvh::BvhNode;
 use camera::Camera;
-use material::{Dielectric, Lambertian, Metal};
-use models::{Model, Sphere};
-use renderer::render_par;
+use denoise::JointBilateral;
+use light::PointLight;
+use material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use models::{Cuboid, Model, MovingSphere, Quad, Sphere};
+use perlin::Perlin;
+use renderer::{render_par, Scene};
+use sky::{Preetham, Sky};
 use std::sync::Arc;
+use texture::{Marble, Turbulence};
 use vector::Vec3;
 
 fn generate_world() -> Box<dyn Model> {
-    let mut rng = rand::thread_rng();
+    random_spheres(false)
+}
+
+// The final scene with the small diffuse spheres bouncing upwards while the shutter is open
+fn bouncing_spheres() -> Box<dyn Model> {
+    random_spheres(true)
+}
+
+fn random_spheres(bouncing: bool) -> Box<dyn Model> {
+    let mut rng = random::rng();
 
     // World
     let mut world: Vec<Arc<dyn Model>> = Vec::new();
@@ -33,7 +59,7 @@ fn generate_world() -> Box<dyn Model> {
         center: Vec3(0.0, -1000.0, 0.0),
         radius: 1000.0,
         material: Box::new(Lambertian {
-            albedo: Vec3(0.5, 0.5, 0.5),
+            albedo: Vec3(0.5, 0.5, 0.5).into(),
         }),
     }));
 
@@ -51,11 +77,25 @@ fn generate_world() -> Box<dyn Model> {
                 if random_mat < 0.8 {
                     // Diffuse
                     let albedo = rng.gen::<Vec3>();
-                    world.push(Arc::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Box::new(Lambertian { albedo }),
-                    }));
+                    let material = Box::new(Lambertian {
+                        albedo: albedo.into(),
+                    });
+                    if bouncing {
+                        world.push(Arc::new(MovingSphere {
+                            center0: center,
+                            center1: center + Vec3(0.0, rng.gen_range(0.0, 0.5), 0.0),
+                            time0: 0.0,
+                            time1: 1.0,
+                            radius: 0.2,
+                            material,
+                        }));
+                    } else {
+                        world.push(Arc::new(Sphere {
+                            center,
+                            radius: 0.2,
+                            material,
+                        }));
+                    }
                 } else if random_mat < 0.95 {
                     // Metal
                     let albedo = Vec3::random(0.5, 1.0);
@@ -63,7 +103,11 @@ fn generate_world() -> Box<dyn Model> {
                     world.push(Arc::new(Sphere {
                         center,
                         radius: 0.2,
-                        material: Box::new(Metal { albedo, fuzz }),
+                        material: Box::new(Metal {
+                            albedo: albedo.into(),
+                            fuzz: fuzz.into(),
+                            thin_film: None,
+                        }),
                     }));
                 } else {
                     world.push(Arc::new(Sphere {
@@ -71,6 +115,9 @@ fn generate_world() -> Box<dyn Model> {
                         radius: 0.2,
                         material: Box::new(Dielectric {
                             refractive_index: 1.5,
+                            absorption: Vec3(0.0, 0.0, 0.0),
+                            dispersion: None,
+                            thin_film: None,
                         }),
                     }));
                 }
@@ -82,6 +129,9 @@ fn generate_world() -> Box<dyn Model> {
         radius: 1.0,
         material: Box::new(Dielectric {
             refractive_index: 1.5,
+            absorption: Vec3(0.0, 0.0, 0.0),
+            dispersion: None,
+            thin_film: None,
         }),
     }));
 
@@ -89,7 +139,7 @@ fn generate_world() -> Box<dyn Model> {
         center: Vec3(-4.0, 1.0, 0.0),
         radius: 1.0,
         material: Box::new(Lambertian {
-            albedo: Vec3(0.4, 0.2, 0.1),
+            albedo: Vec3(0.4, 0.2, 0.1).into(),
         }),
     }));
 
@@ -97,8 +147,9 @@ fn generate_world() -> Box<dyn Model> {
         center: Vec3(4.0, 1.0, 0.0),
         radius: 1.0,
         material: Box::new(Metal {
-            albedo: Vec3(0.7, 0.6, 0.5),
-            fuzz: 0.0,
+            albedo: Vec3(0.7, 0.6, 0.5).into(),
+            fuzz: 0.0.into(),
+            thin_film: None,
         }),
     }));
 
@@ -114,7 +165,7 @@ fn glass_test() -> Box<dyn Model> {
         center: Vec3(0.0, -100.5, -1.0),
         radius: 100.0,
         material: Box::new(Lambertian {
-            albedo: Vec3(0.8, 0.8, 0.0),
+            albedo: Vec3(0.8, 0.8, 0.0).into(),
         }),
     }));
 
@@ -123,6 +174,9 @@ fn glass_test() -> Box<dyn Model> {
         radius: 0.5,
         material: Box::new(Dielectric {
             refractive_index: 1.5,
+            absorption: Vec3(0.0, 0.0, 0.0),
+            dispersion: None,
+            thin_film: None,
         }),
     }));
 
@@ -131,6 +185,9 @@ fn glass_test() -> Box<dyn Model> {
         radius: 0.5,
         material: Box::new(Dielectric {
             refractive_index: 1.5,
+            absorption: Vec3(0.0, 0.0, 0.0),
+            dispersion: None,
+            thin_film: None,
         }),
     }));
 
@@ -138,20 +195,168 @@ fn glass_test() -> Box<dyn Model> {
         center: Vec3(1.0, 0.0, -1.0),
         radius: 0.5,
         material: Box::new(Metal {
-            albedo: Vec3(0.7, 0.6, 0.5),
-            fuzz: 0.0,
+            albedo: Vec3(0.7, 0.6, 0.5).into(),
+            fuzz: 0.0.into(),
+            thin_film: None,
         }),
     }));
     Box::new( world)
 }
 
+// Scene units are millimeters, to be viewed from (278, 278, -800) looking at (278, 278, 0)
+fn cornell_box() -> Box<dyn Model> {
+    let mut world: Vec<Arc<dyn Model>> = Vec::new();
+
+    let diffuse = |albedo: Vec3| -> Box<dyn Material> {
+        Box::new(Lambertian {
+            albedo: albedo.into(),
+        })
+    };
+    let red = Vec3(0.65, 0.05, 0.05);
+    let white = Vec3(0.73, 0.73, 0.73);
+    let green = Vec3(0.12, 0.45, 0.15);
+
+    // Walls
+    world.push(Arc::new(Quad::yz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        diffuse(green),
+    )));
+    world.push(Arc::new(Quad::yz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        diffuse(red),
+    )));
+    world.push(Arc::new(Quad::xz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        diffuse(white),
+    )));
+    world.push(Arc::new(Quad::xz(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        diffuse(white),
+    )));
+    world.push(Arc::new(Quad::xy(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        diffuse(white),
+    )));
+
+    // Light, just below the ceiling
+    world.push(Arc::new(Quad::xz(
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        Box::new(DiffuseLight {
+            emit: Vec3(15.0, 15.0, 15.0).into(),
+        }),
+    )));
+
+    // Blocks
+    world.push(Arc::new(Cuboid::new(
+        Vec3(130.0, 0.0, 65.0),
+        Vec3(295.0, 165.0, 230.0),
+        diffuse(white),
+    )));
+    world.push(Arc::new(Cuboid::new(
+        Vec3(265.0, 0.0, 295.0),
+        Vec3(430.0, 330.0, 460.0),
+        diffuse(white),
+    )));
+
+    Box::new(world)
+}
+
+fn perlin_spheres() -> Box<dyn Model> {
+    // World
+    let mut world: Vec<Arc<dyn Model>> = Vec::new();
+
+    // Ground
+    world.push(Arc::new(Sphere {
+        center: Vec3(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Box::new(Lambertian {
+            albedo: Box::new(Turbulence {
+                perlin: Perlin::new(),
+                scale: 4.0,
+                depth: 7,
+            }),
+        }),
+    }));
+
+    world.push(Arc::new(Sphere {
+        center: Vec3(0.0, 2.0, 0.0),
+        radius: 2.0,
+        material: Box::new(Lambertian {
+            albedo: Box::new(Marble {
+                perlin: Perlin::new(),
+                scale: 4.0,
+                depth: 7,
+                color: Vec3(1.0, 1.0, 1.0),
+            }),
+        }),
+    }));
+
+    world.push(Arc::new(Sphere {
+        center: Vec3(4.0, 1.0, 2.0),
+        radius: 1.0,
+        material: Box::new(Metal {
+            albedo: Vec3(0.8, 0.8, 0.8).into(),
+            fuzz: Box::new(Turbulence {
+                perlin: Perlin::new(),
+                scale: 3.0,
+                depth: 4,
+            }),
+            thin_film: None,
+        }),
+    }));
+    Box::new(world)
+}
+
+// The Perlin spheres in daylight, with a warm point light off to the side
+fn daylight_spheres() -> Scene {
+    Scene {
+        world: perlin_spheres(),
+        sky: Sky::Preetham(Preetham::new(Vec3(1.0, 0.6, 0.3), 3.0)),
+        lights: vec![Box::new(PointLight {
+            position: Vec3(3.0, 5.0, -3.0),
+            intensity: Vec3(12.0, 9.0, 6.0),
+            casts_shadows: true,
+        })],
+    }
+}
+
 fn main() {
     const ASPECT_RATIO: f64 = 3.0 / 2.0;
     const IMAGE_WIDTH: usize = 1200;
     const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as usize;
     const SAMPLES_PER_PIXEL: usize = 500;
+    // Worth enabling for low sample count previews
+    const DENOISE: bool = false;
 
-    let world = generate_world();
+    let scene = Scene {
+        world: generate_world(),
+        sky: Sky::Gradient,
+        lights: Vec::new(),
+    };
 
     let camera = Camera::new(
         Vec3(13.0, 2.0, 3.0),
@@ -161,15 +366,25 @@ fn main() {
         ASPECT_RATIO,
         0.1,
         10.0,
+        0.0,
+        1.0,
     );
 
+    let denoiser = if DENOISE {
+        Some(JointBilateral::default())
+    } else {
+        None
+    };
+
     eprintln!("Rendering image...");
     let image = render_par(
-        world,
+        scene,
         Box::from(camera),
         IMAGE_WIDTH,
         IMAGE_HEIGHT,
         SAMPLES_PER_PIXEL,
+        denoiser,
+        None,
     );
 
     let path: &Path = Path::new(r"render.png");
@@ -192,3 +407,55 @@ fn main() {
 
     streamwriter.finish().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mean of each channel over a small fixed seed render of the Cornell box
+    const CORNELL_BOX_MEANS: [f64; 3] = [36.210, 30.797, 26.105];
+
+    #[test]
+    fn test_cornell_box_reference() {
+        const SIZE: usize = 32;
+
+        let scene = Scene {
+            world: cornell_box(),
+            sky: Sky::Solid(Vec3(0.0, 0.0, 0.0)),
+            lights: Vec::new(),
+        };
+        let camera = Camera::new(
+            Vec3(278.0, 278.0, -800.0),
+            Vec3(278.0, 278.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+            0.0,
+            0.0,
+        );
+
+        let image = render_par(scene, Box::new(camera), SIZE, SIZE, 16, None, Some(1));
+
+        let mut sum = [0.0; 3];
+        for line in image.lock().unwrap().iter() {
+            for pixel in line {
+                sum[0] += pixel.0 as f64;
+                sum[1] += pixel.1 as f64;
+                sum[2] += pixel.2 as f64;
+            }
+        }
+
+        for (channel, sum) in sum.iter().enumerate() {
+            let mean = sum / (SIZE * SIZE) as f64;
+            assert!(
+                (mean - CORNELL_BOX_MEANS[channel]).abs() < 0.5,
+                "channel {} has mean {}, expected {}",
+                channel,
+                mean,
+                CORNELL_BOX_MEANS[channel]
+            );
+        }
+    }
+}
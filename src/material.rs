@@ -1,6 +1,17 @@
+use std::f64::consts::PI;
+use std::ops;
+
 use rand::Rng;
 
+use super::medium::{HenyeyGreenstein, Homogeneous, Medium};
+use super::microfacet::{
+    fresnel_dielectric, fresnel_schlick, sample_cosine_hemisphere, Frame, Ggx,
+};
 use super::models::Hit;
+use super::random;
+use super::spectrum::Dispersion;
+use super::texture::Texture;
+use super::thin_film::{Substrate, ThinFilm};
 use super::vector::{Ray, Vec3};
 
 pub struct Scatter {
@@ -8,24 +19,228 @@ pub struct Scatter {
     pub ray: Ray,
 }
 
+// Kinds of lobe in a BSDF. Specular lobes are deltas that can only be sampled, so integrators
+// must not try to evaluate them for directions they picked themselves.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BsdfFlags(u8);
+
+impl BsdfFlags {
+    pub const DIFFUSE: BsdfFlags = BsdfFlags(1);
+    pub const GLOSSY: BsdfFlags = BsdfFlags(1 << 1);
+    pub const SPECULAR: BsdfFlags = BsdfFlags(1 << 2);
+    pub const TRANSMISSION: BsdfFlags = BsdfFlags(1 << 3);
+    // Straight on through as if the surface weren't there, like cutout holes and medium
+    // boundaries. Rays keep seeing the world the way they did before.
+    pub const PASS_THROUGH: BsdfFlags = BsdfFlags(1 << 4);
+
+    pub fn contains(self, other: BsdfFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // True when nothing in the BSDF can be evaluated
+    pub fn is_delta(self) -> bool {
+        !self.contains(BsdfFlags::DIFFUSE) && !self.contains(BsdfFlags::GLOSSY)
+    }
+}
+
+impl ops::BitOr for BsdfFlags {
+    type Output = BsdfFlags;
+
+    fn bitor(self, rhs: BsdfFlags) -> BsdfFlags {
+        BsdfFlags(self.0 | rhs.0)
+    }
+}
+
+pub struct BsdfSample {
+    // Attenuation is the BSDF times the cosine over the pdf
+    pub scatter: Scatter,
+    // Solid angle density of the sampled direction, 1 for delta lobes
+    pub pdf: f64,
+    // The lobe the direction came from
+    pub lobe: BsdfFlags,
+}
+
+// Directions passed to eval and pdf are unit vectors pointing away from the surface: wo towards
+// where the light ends up (the previous vertex of the path), wi towards where it came from.
 pub trait Material: Send + Sync {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter>;
+    // Picks the direction a path carries on in, with its weight, density and lobe
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample>;
+
+    // Just the direction and weight of a sample
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
+        self.sample(r_in, hit).map(|sample| sample.scatter)
+    }
+
+    // BSDF value, not including the cosine term. Always zero for delta lobes.
+    fn eval(&self, _hit: &Hit, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3(0.0, 0.0, 0.0)
+    }
+
+    // Density with which sample would pick wi
+    fn pdf(&self, _hit: &Hit, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::SPECULAR
+    }
+
+    // Surface color at the hit, used as a feature buffer by the denoiser
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        Vec3(1.0, 1.0, 1.0)
+    }
+
+    // Medium filling the inside of closed surfaces made of this material
+    fn interior(&self) -> Option<&dyn Medium> {
+        None
+    }
+
+    // Chance of a ray carrying straight on through the surface, which shadow rays see through
+    fn pass_through(&self, _hit: &Hit) -> f64 {
+        0.0
+    }
+
+    // Radiance given off by the surface itself
+    fn emitted(&self, _hit: &Hit) -> Vec3 {
+        Vec3(0.0, 0.0, 0.0)
+    }
+}
+
+// Whether a pair of directions lies on the lit side of a reflective surface
+fn reflects(hit: &Hit, wo: Vec3, wi: Vec3) -> bool {
+    Vec3::dot(wo, hit.shading_normal) > 0.0
+        && Vec3::dot(wi, hit.shading_normal) > 0.0
+        && Vec3::dot(wi, hit.normal) > 0.0
 }
 
 pub struct Lambertian {
-    pub albedo: Vec3,
+    pub albedo: Box<dyn Texture>,
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, hit: &Hit) -> Option<Scatter> {
-        Some(Scatter {
-            attenuation: self.albedo,
-            ray: Ray {
-                origin: hit.point,
-                direction: hit.normal + Vec3::random_unit_vector(),
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        // Offsetting the normal by a point on the unit sphere gives a cosine distribution
+        let direction = hit.shading_normal + Vec3::random_unit_vector();
+
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: self.albedo(hit),
+                ray: Ray {
+                    origin: hit.point,
+                    direction,
+                    wavelength: r_in.wavelength,
+                    time: r_in.time,
+                },
+            },
+            pdf: Vec3::dot(direction.unit(), hit.shading_normal).max(0.0) / PI,
+            lobe: BsdfFlags::DIFFUSE,
+        })
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        if reflects(hit, wo, wi) {
+            (1.0 / PI) * self.albedo(hit)
+        } else {
+            Vec3(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn pdf(&self, hit: &Hit, _wo: Vec3, wi: Vec3) -> f64 {
+        Vec3::dot(wi, hit.shading_normal).max(0.0) / PI
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.albedo.value(hit.u, hit.v, hit.point)
+    }
+}
+
+// Rough diffuse surface made of Lambertian facets (Oren and Nayar), which gets flatter and more
+// retroreflective than Lambertian as sigma grows, like clay, concrete or cloth
+pub struct OrenNayar {
+    pub albedo: Box<dyn Texture>,
+    // Standard deviation of the facet angles, in degrees
+    pub sigma: f64,
+}
+
+impl OrenNayar {
+    // The BRDF without the albedo / pi factor
+    fn reflectance(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let sigma = self.sigma.to_radians();
+        let sigma_sqr = sigma * sigma;
+        let a = 1.0 - sigma_sqr / (2.0 * (sigma_sqr + 0.33));
+        let b = 0.45 * sigma_sqr / (sigma_sqr + 0.09);
+
+        let sin_theta_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+        let sin_theta_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+
+        let max_cos = if sin_theta_o > 1e-4 && sin_theta_i > 1e-4 {
+            ((wi.x() * wo.x() + wi.y() * wo.y()) / (sin_theta_i * sin_theta_o)).max(0.0)
+        } else {
+            0.0
+        };
+
+        let (sin_alpha, tan_beta) = if wi.z() > wo.z() {
+            (sin_theta_o, sin_theta_i / wi.z())
+        } else {
+            (sin_theta_i, sin_theta_o / wo.z())
+        };
+
+        a + b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wo = frame.to_local(-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let mut rng = random::rng();
+        let wi = sample_cosine_hemisphere(rng.gen(), rng.gen());
+
+        // Cosine sampling cancels everything but the albedo and the facet term
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: self.reflectance(wo, wi) * self.albedo(hit),
+                ray: Ray {
+                    origin: hit.point,
+                    direction: frame.to_world(wi),
+                    wavelength: r_in.wavelength,
+                    time: r_in.time,
+                },
             },
+            pdf: wi.z() / PI,
+            lobe: BsdfFlags::DIFFUSE,
         })
     }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        if !reflects(hit, wo, wi) {
+            return Vec3(0.0, 0.0, 0.0);
+        }
+
+        let frame = Frame::from_normal(hit.shading_normal);
+        let reflectance = self.reflectance(frame.to_local(wo), frame.to_local(wi));
+        (reflectance / PI) * self.albedo(hit)
+    }
+
+    fn pdf(&self, hit: &Hit, _wo: Vec3, wi: Vec3) -> f64 {
+        Vec3::dot(wi, hit.shading_normal).max(0.0) / PI
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.albedo.value(hit.u, hit.v, hit.point)
+    }
 }
 
 pub fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
@@ -33,26 +248,130 @@ pub fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
 }
 
 pub struct Metal {
-    pub albedo: Vec3,
-    pub fuzz: f64,
+    pub albedo: Box<dyn Texture>,
+    pub fuzz: Box<dyn Texture>,
+    // Iridescent coating, like heat tint or oil on the metal
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Metal {
+    // Spectral paths pass each of their wavelengths, and see the film at just that one
+    fn reflectance(&self, hit: &Hit, wo: Vec3, wavelength: Option<f64>) -> Vec3 {
+        let albedo = self.albedo(hit);
+        match &self.thin_film {
+            Some(film) => {
+                let cos_theta = Vec3::dot(wo, hit.shading_normal);
+                let substrate = Substrate::Conductor(albedo);
+
+                match wavelength {
+                    Some(lambda) => Vec3(
+                        film.reflectance(cos_theta, 1.0, substrate, 0, lambda),
+                        film.reflectance(cos_theta, 1.0, substrate, 1, lambda),
+                        film.reflectance(cos_theta, 1.0, substrate, 2, lambda),
+                    ),
+                    _ => film.reflectance_rgb(cos_theta, 1.0, substrate),
+                }
+            }
+            _ => albedo,
+        }
+    }
+
+    // Density of fuzzed directions around the mirror direction. The ray is aimed at a uniform
+    // point in a ball of radius fuzz around the end of the unit reflection, so the density is the
+    // share of the ball's volume lying along wi.
+    fn fuzz_pdf(reflected: Vec3, fuzz: f64, wi: Vec3) -> f64 {
+        let cos = Vec3::dot(wi, reflected);
+        let discriminant = fuzz * fuzz - (1.0 - cos * cos);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let root = discriminant.sqrt();
+        let far = cos + root;
+        let near = (cos - root).max(0.0);
+        if far <= 0.0 {
+            return 0.0;
+        }
+
+        (far.powi(3) - near.powi(3)) / (4.0 * PI * fuzz.powi(3))
+    }
 }
 
+// Fuzz is a texture, so whether a hit is a perfect mirror is only known per hit. Flags report both
+// lobes, and eval and pdf are zero where the fuzz is.
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
-        let reflected = reflect(r_in.direction, hit.normal);
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let fuzz = self.fuzz.value(hit.u, hit.v, hit.point).x();
+        let reflected = reflect(r_in.direction.unit(), hit.shading_normal);
         let scattered = Ray {
             origin: hit.point,
-            direction: reflected + (self.fuzz * Vec3::random_unit_sphere()),
+            direction: reflected + (fuzz * Vec3::random_unit_sphere()),
+            wavelength: r_in.wavelength,
+            time: r_in.time,
         };
 
-        if Vec3::dot(scattered.direction, hit.normal) > 0.0 {
-            Some(Scatter {
-                attenuation: self.albedo,
-                ray: scattered,
-            })
+        // Checked against the geometric normal so shading normals can't send rays into the surface
+        if Vec3::dot(scattered.direction, hit.normal) <= 0.0 {
+            return None;
+        }
+
+        let (pdf, lobe) = if fuzz > 0.0 {
+            let wi = scattered.direction.unit();
+            (Metal::fuzz_pdf(reflected, fuzz, wi), BsdfFlags::GLOSSY)
+        } else {
+            (1.0, BsdfFlags::SPECULAR)
+        };
+
+        // Sampling follows the fuzz exactly, so only the reflectance is left in the weight. Spectral
+        // paths see the film per wavelength.
+        let wo = -r_in.direction.unit();
+        let mut wavelength = r_in.wavelength;
+        let attenuation = if self.thin_film.is_some() && wavelength.is_spectral() {
+            wavelength.scale(|lambda| (self.reflectance(hit, wo, Some(lambda)), 1.0));
+            Vec3(1.0, 1.0, 1.0)
         } else {
-            None
+            self.reflectance(hit, wo, None)
+        };
+
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation,
+                ray: Ray {
+                    wavelength,
+                    ..scattered
+                },
+            },
+            pdf,
+            lobe,
+        })
+    }
+
+    // Whatever the density, the weight is the reflectance, which makes the BRDF the reflectance
+    // times the density over the cosine. There's no path here, so thin films are seen in RGB.
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        let cos_i = Vec3::dot(wi, hit.shading_normal);
+        if !reflects(hit, wo, wi) || cos_i <= 1e-6 {
+            return Vec3(0.0, 0.0, 0.0);
         }
+
+        (self.pdf(hit, wo, wi) / cos_i) * self.reflectance(hit, wo, None)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        let fuzz = self.fuzz.value(hit.u, hit.v, hit.point).x();
+        if fuzz <= 0.0 || Vec3::dot(wi, hit.normal) <= 0.0 {
+            return 0.0;
+        }
+
+        Metal::fuzz_pdf(reflect(-wo, hit.shading_normal), fuzz, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::GLOSSY | BsdfFlags::SPECULAR
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.albedo.value(hit.u, hit.v, hit.point)
     }
 }
 
@@ -63,8 +382,29 @@ pub fn refract(uv: Vec3, normal: Vec3, eta_over_etaprime: f64) -> Vec3 {
     r_out_perpendicular + r_out_parallel
 }
 
+// Fraction of light surviving a trip through an absorbing medium, by the Beer-Lambert law.
+// Only rays leaving the object have travelled through its interior.
+fn beer_lambert(absorption: Vec3, r_in: &Ray, hit: &Hit) -> Vec3 {
+    if hit.front_face {
+        return Vec3(1.0, 1.0, 1.0);
+    }
+
+    let distance = hit.t * r_in.direction.len();
+    Vec3(
+        (-absorption.x() * distance).exp(),
+        (-absorption.y() * distance).exp(),
+        (-absorption.z() * distance).exp(),
+    )
+}
+
 pub struct Dielectric {
     pub refractive_index: f64,
+    // Absorption coefficient per unit of distance travelled inside, zero for clear glass
+    pub absorption: Vec3,
+    // Overrides refractive_index with one that depends on the ray's wavelength
+    pub dispersion: Option<Dispersion>,
+    // Interference coating on the outside of the surface, for soap bubbles
+    pub thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
@@ -74,49 +414,1149 @@ impl Dielectric {
 
         r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
     }
+
+    fn refractive_index(&self, lambda: f64) -> f64 {
+        match &self.dispersion {
+            Some(dispersion) => dispersion.refractive_index(lambda),
+            _ => self.refractive_index,
+        }
+    }
+
+    // Where the film sits relative to the glass. It's on the outside, so light from within
+    // crosses the glass first.
+    fn film_sides(hit: &Hit, refractive_index: f64) -> (f64, Substrate) {
+        if hit.front_face {
+            (1.0, Substrate::Dielectric(refractive_index))
+        } else {
+            (refractive_index, Substrate::Dielectric(1.0))
+        }
+    }
+
+    // Chance of light of one wavelength reflecting rather than refracting
+    fn reflectance(&self, hit: &Hit, cos_theta: f64, lambda: f64) -> f64 {
+        let refractive_index = self.refractive_index(lambda);
+        let etai_over_etat = if hit.front_face {
+            1.0 / refractive_index
+        } else {
+            refractive_index
+        };
+
+        let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
+        if (etai_over_etat * sin_theta) > 1.0 {
+            return 1.0;
+        }
+
+        match &self.thin_film {
+            Some(film) => {
+                let (outer_index, substrate) = Dielectric::film_sides(hit, refractive_index);
+                film.reflectance(cos_theta, outer_index, substrate, 0, lambda)
+            }
+            _ => Dielectric::schlick(cos_theta, etai_over_etat),
+        }
+    }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
-        let attenuation = Vec3(1.0, 1.0, 1.0);
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let mut attenuation = beer_lambert(self.absorption, r_in, hit);
+        let mut wavelength = r_in.wavelength;
 
+        let refractive_index = self.refractive_index(wavelength.hero());
         let etai_over_etat = if hit.front_face {
-            1.0 / self.refractive_index
+            1.0 / refractive_index
         } else {
-            self.refractive_index
+            refractive_index
         };
 
         let unit_direction = r_in.direction.unit();
 
-        let cos_theta = Vec3::dot(-unit_direction, hit.normal).min(1.0);
+        let cos_theta = Vec3::dot(-unit_direction, hit.shading_normal).min(1.0);
         let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
 
-        let reflected = reflect(unit_direction, hit.normal);
-        let reflect_scatter = Some(Scatter {
-            attenuation,
-            ray: Ray {
-                origin: hit.point,
-                direction: reflected,
+        let reflected = reflect(unit_direction, hit.shading_normal);
+        let reflect_sample = |attenuation, wavelength| {
+            Some(BsdfSample {
+                scatter: Scatter {
+                    attenuation,
+                    ray: Ray {
+                        origin: hit.point,
+                        direction: reflected,
+                        wavelength,
+                        time: r_in.time,
+                    },
+                },
+                pdf: 1.0,
+                lobe: BsdfFlags::SPECULAR,
+            })
+        };
+
+        let mut rng = random::rng();
+
+        // Reflectance that depends on wavelength is followed per wavelength. The hero picks the
+        // lobe, and each wavelength keeps how likely it was to pick the same one.
+        let spectral =
+            self.dispersion.is_some() || (self.thin_film.is_some() && wavelength.is_spectral());
+
+        if spectral {
+            let reflectance = |lambda| self.reflectance(hit, cos_theta, lambda);
+            if rng.gen::<f64>() < reflectance(wavelength.hero()) {
+                wavelength.scale(|lambda| {
+                    let r = reflectance(lambda);
+                    (Vec3(r, r, r), r)
+                });
+                return reflect_sample(attenuation, wavelength);
+            }
+
+            wavelength.scale(|lambda| {
+                let t = 1.0 - reflectance(lambda);
+                (Vec3(t, t, t), t)
+            });
+            // Each wavelength bends its own way, and only the hero's is being followed
+            if self.dispersion.is_some() {
+                wavelength.follow_hero();
+            }
+        } else {
+            if (etai_over_etat * sin_theta) > 1.0 {
+                return reflect_sample(attenuation, wavelength);
+            }
+
+            match &self.thin_film {
+                Some(film) => {
+                    let (outer_index, substrate) = Dielectric::film_sides(hit, refractive_index);
+                    let reflectance = film.reflectance_rgb(cos_theta, outer_index, substrate);
+
+                    // Choose between the lobes by mean reflectance, weighting for the color shift
+                    let probability = num::clamp(
+                        (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0,
+                        1e-4,
+                        1.0 - 1e-4,
+                    );
+
+                    if rng.gen::<f64>() < probability {
+                        return reflect_sample(attenuation * reflectance / probability, wavelength);
+                    }
+
+                    attenuation =
+                        attenuation * (Vec3(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability);
+                }
+                _ => {
+                    if rng.gen::<f64>() < Dielectric::schlick(cos_theta, etai_over_etat) {
+                        return reflect_sample(attenuation, wavelength);
+                    }
+                }
+            }
+        }
+
+        let refracted = refract(unit_direction, hit.shading_normal, etai_over_etat);
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation,
+                ray: Ray {
+                    origin: hit.point,
+                    direction: refracted,
+                    wavelength,
+                    time: r_in.time,
+                },
             },
-        });
+            pdf: 1.0,
+            lobe: BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION,
+        })
+    }
 
-        if (etai_over_etat * sin_theta) > 1.0 {
-            return reflect_scatter;
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION
+    }
+}
+
+// Glass with a microscopically rough interface (Walter et al. 2007), for frosted and etched looks
+pub struct RoughDielectric {
+    pub refractive_index: f64,
+    pub roughness: Box<dyn Texture>,
+    pub absorption: Vec3,
+}
+
+impl RoughDielectric {
+    fn eta(&self, hit: &Hit) -> f64 {
+        if hit.front_face {
+            1.0 / self.refractive_index
+        } else {
+            self.refractive_index
+        }
+    }
+
+    // Microfacet normal that takes wo to wi, facing out of the surface, and whether that's by
+    // reflection. Refraction has eta * wo + wi along the normal.
+    fn half_vector(eta: f64, wo: Vec3, wi: Vec3) -> (Vec3, bool) {
+        let reflecting = wi.z() > 0.0;
+        let m = if reflecting { wo + wi } else { eta * wo + wi };
+        let m = m.unit();
+
+        (if m.z() < 0.0 { -m } else { m }, reflecting)
+    }
+
+    // BSDF and density in the local frame. Transmission leaves out the 1 / eta^2 scaling of
+    // radiance, the same as sample does.
+    fn eval_pdf(&self, ggx: &Ggx, eta: f64, wo: Vec3, wi: Vec3) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let (m, reflecting) = RoughDielectric::half_vector(eta, wo, wi);
+        let cos_o = Vec3::dot(wo, m);
+        let cos_i = Vec3::dot(wi, m);
+        if cos_o <= 0.0 || (reflecting && cos_i <= 0.0) || (!reflecting && cos_i >= 0.0) {
+            return (0.0, 0.0);
+        }
+
+        let fresnel = fresnel_dielectric(cos_o, eta);
+        let d = ggx.d(m);
+        let g = ggx.g2(wo, wi);
+        let visible = ggx.pdf_visible(wo, m);
+
+        if reflecting {
+            (
+                fresnel * d * g / (4.0 * wo.z() * wi.z()),
+                fresnel * visible / (4.0 * cos_o),
+            )
+        } else {
+            // Change of variables from the microfacet normal to the refracted direction
+            let denominator = (eta * cos_o + cos_i).powi(2);
+            let jacobian = cos_i.abs() / denominator;
+            (
+                (1.0 - fresnel) * d * g * cos_o * jacobian / (wo.z() * wi.z().abs()),
+                (1.0 - fresnel) * visible * jacobian,
+            )
+        }
+    }
+
+    // Geometric side wi has to be on to go with the lobe it came from
+    fn sides_agree(hit: &Hit, wi_local: Vec3, wi: Vec3) -> bool {
+        let side = Vec3::dot(wi, hit.normal);
+        (wi_local.z() > 0.0 && side > 0.0) || (wi_local.z() < 0.0 && side < 0.0)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let etai_over_etat = self.eta(hit);
+
+        let ggx = Ggx::from_roughness(self.roughness.value(hit.u, hit.v, hit.point).x());
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wo = frame.to_local(-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let mut rng = random::rng();
+        let m = ggx.sample_visible(wo, rng.gen(), rng.gen());
+        let cos_theta = Vec3::dot(wo, m);
+
+        // Choosing reflection or refraction by the microfacet's Fresnel term cancels it from
+        // the weight, which leaves only the shadowing of the outgoing direction
+        let reflecting = rng.gen::<f64>() < fresnel_dielectric(cos_theta, etai_over_etat);
+        let wi = if reflecting {
+            2.0 * cos_theta * m - wo
+        } else {
+            refract(-wo, m, etai_over_etat)
+        };
+
+        let direction = frame.to_world(wi);
+        if reflecting != (wi.z() > 0.0) || !RoughDielectric::sides_agree(hit, wi, direction) {
+            return None;
+        }
+
+        let (_, pdf) = self.eval_pdf(&ggx, etai_over_etat, wo, wi);
+        let lobe = if reflecting {
+            BsdfFlags::GLOSSY
+        } else {
+            BsdfFlags::GLOSSY | BsdfFlags::TRANSMISSION
+        };
+
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: (ggx.g2(wo, wi) / ggx.g1(wo))
+                    * beer_lambert(self.absorption, r_in, hit),
+                ray: Ray {
+                    origin: hit.point,
+                    direction,
+                    wavelength: r_in.wavelength,
+                    time: r_in.time,
+                },
+            },
+            pdf,
+            lobe,
+        })
+    }
+
+    // Absorption depends on how far the ray travelled inside, which only sample knows, so light
+    // sampled from inside absorbing glass isn't tinted by the way there
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wi_local = frame.to_local(wi);
+        if !RoughDielectric::sides_agree(hit, wi_local, wi) {
+            return Vec3(0.0, 0.0, 0.0);
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness.value(hit.u, hit.v, hit.point).x());
+        let (f, _) = self.eval_pdf(&ggx, self.eta(hit), frame.to_local(wo), wi_local);
+        Vec3(f, f, f)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wi_local = frame.to_local(wi);
+        if !RoughDielectric::sides_agree(hit, wi_local, wi) {
+            return 0.0;
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness.value(hit.u, hit.v, hit.point).x());
+        self.eval_pdf(&ggx, self.eta(hit), frame.to_local(wo), wi_local)
+            .1
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::GLOSSY | BsdfFlags::TRANSMISSION
+    }
+}
+
+// Metallic-roughness model from glTF: a GGX specular lobe over a diffuse base, with metals
+// tinting the specular reflection and having no diffuse term
+pub struct MetallicRoughness {
+    pub base_color: Box<dyn Texture>,
+    pub metallic: f64,
+    pub roughness: f64,
+    // Optional glTF packed texture, roughness in green and metalness in blue, scaling the factors
+    pub metallic_roughness: Option<Box<dyn Texture>>,
+}
+
+// Surface parameters of MetallicRoughness resolved at a hit
+struct PbrParams {
+    diffuse: Vec3,
+    f0: Vec3,
+    ggx: Ggx,
+}
+
+impl PbrParams {
+    fn specular_probability(&self, cos_o: f64) -> f64 {
+        let specular = fresnel_schlick(self.f0, cos_o).accumulate();
+        let diffuse = self.diffuse.accumulate();
+
+        if specular + diffuse <= 0.0 {
+            1.0
+        } else {
+            specular / (specular + diffuse)
+        }
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3(0.0, 0.0, 0.0);
+        }
+
+        let m = (wo + wi).unit();
+        let fresnel = fresnel_schlick(self.f0, Vec3::dot(wi, m));
+        let specular = (self.ggx.d(m) * self.ggx.g2(wo, wi) / (4.0 * wo.z() * wi.z())) * fresnel;
+        let diffuse = (1.0 / PI) * (Vec3(1.0, 1.0, 1.0) - fresnel) * self.diffuse;
+
+        specular + diffuse
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let m = (wo + wi).unit();
+        let p_specular = self.specular_probability(wo.z());
+        let specular = self.ggx.pdf_visible(wo, m) / (4.0 * Vec3::dot(wo, m));
+        let diffuse = wi.z() / PI;
+
+        p_specular * specular + (1.0 - p_specular) * diffuse
+    }
+}
+
+impl MetallicRoughness {
+    fn params(&self, hit: &Hit) -> PbrParams {
+        let base_color = self.base_color.value(hit.u, hit.v, hit.point);
+        let (metallic, roughness) = match &self.metallic_roughness {
+            Some(texture) => {
+                let packed = texture.value(hit.u, hit.v, hit.point);
+                (self.metallic * packed.z(), self.roughness * packed.y())
+            }
+            _ => (self.metallic, self.roughness),
+        };
+
+        PbrParams {
+            diffuse: (1.0 - metallic) * base_color,
+            f0: (1.0 - metallic) * Vec3(0.04, 0.04, 0.04) + metallic * base_color,
+            ggx: Ggx::from_roughness(roughness),
+        }
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let params = self.params(hit);
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wo = frame.to_local(-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // Pick a lobe, but weight by the density of the whole BSDF so the choice only affects noise
+        let mut rng = random::rng();
+        let (wi, lobe) = if rng.gen::<f64>() < params.specular_probability(wo.z()) {
+            let m = params.ggx.sample_visible(wo, rng.gen(), rng.gen());
+            (2.0 * Vec3::dot(wo, m) * m - wo, BsdfFlags::GLOSSY)
+        } else {
+            (
+                sample_cosine_hemisphere(rng.gen(), rng.gen()),
+                BsdfFlags::DIFFUSE,
+            )
+        };
+
+        let pdf = params.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let direction = frame.to_world(wi);
+        if Vec3::dot(direction, hit.normal) <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: (wi.z() / pdf) * params.eval(wo, wi),
+                ray: Ray {
+                    origin: hit.point,
+                    direction,
+                    wavelength: r_in.wavelength,
+                    time: r_in.time,
+                },
+            },
+            pdf,
+            lobe,
+        })
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        if !reflects(hit, wo, wi) {
+            return Vec3(0.0, 0.0, 0.0);
+        }
+
+        let frame = Frame::from_normal(hit.shading_normal);
+        self.params(hit)
+            .eval(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        let frame = Frame::from_normal(hit.shading_normal);
+        self.params(hit).pdf(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE | BsdfFlags::GLOSSY
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.base_color.value(hit.u, hit.v, hit.point)
+    }
+}
+
+// Clear dielectric coat over another material, like car paint or varnished wood. The coat
+// reflection and the base are picked stochastically by the coat's Fresnel reflectance, and light
+// reaching the base loses what the coat reflects on the way in and out.
+pub struct Coated {
+    pub refractive_index: f64,
+    pub roughness: Box<dyn Texture>,
+    pub base: Box<dyn Material>,
+}
+
+impl Coated {
+    fn ggx(&self, hit: &Hit) -> Ggx {
+        Ggx::from_roughness(self.roughness.value(hit.u, hit.v, hit.point).x())
+    }
+
+    // The coat's reflection on its own, in the local frame
+    fn coat_eval_pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let ggx = self.ggx(hit);
+        let m = (wo + wi).unit();
+        let cos_theta = Vec3::dot(wo, m);
+        let fresnel = fresnel_dielectric(cos_theta, 1.0 / self.refractive_index);
+
+        (
+            fresnel * ggx.d(m) * ggx.g2(wo, wi) / (4.0 * wo.z() * wi.z()),
+            ggx.pdf_visible(wo, m) / (4.0 * cos_theta),
+        )
+    }
+}
+
+// The coat is picked with the chance of its reflectance towards wo, which is also how much light
+// it keeps from the base. That cancels from the base's weight, leaving the base lit through the
+// coat in both directions: coat + (1 - F(wo)) (1 - F(wi)) base.
+impl Material for Coated {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        // Coats are only ever seen from outside
+        if !hit.front_face {
+            return self.base.sample(r_in, hit);
+        }
+
+        let eta = 1.0 / self.refractive_index;
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wo = frame.to_local(-r_in.direction.unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let mut rng = random::rng();
+        let p_coat = fresnel_dielectric(wo.z(), eta);
+
+        if rng.gen::<f64>() < p_coat {
+            let ggx = self.ggx(hit);
+            let m = ggx.sample_visible(wo, rng.gen(), rng.gen());
+            let cos_theta = Vec3::dot(wo, m);
+            let wi = 2.0 * cos_theta * m - wo;
+
+            let direction = frame.to_world(wi);
+            if wi.z() <= 0.0 || Vec3::dot(direction, hit.normal) <= 0.0 {
+                return None;
+            }
+
+            let weight = fresnel_dielectric(cos_theta, eta) * ggx.g2(wo, wi) / ggx.g1(wo) / p_coat;
+            Some(BsdfSample {
+                scatter: Scatter {
+                    attenuation: weight * Vec3(1.0, 1.0, 1.0),
+                    ray: Ray {
+                        origin: hit.point,
+                        direction,
+                        wavelength: r_in.wavelength,
+                        time: r_in.time,
+                    },
+                },
+                pdf: self.pdf(hit, -r_in.direction.unit(), direction),
+                lobe: BsdfFlags::GLOSSY,
+            })
+        } else {
+            // Transmission on the way in cancels with the chance of picking the base
+            let sample = self.base.sample(r_in, hit)?;
+            let wi = sample.scatter.ray.direction.unit();
+            let transmitted = 1.0 - fresnel_dielectric(Vec3::dot(wi, hit.shading_normal), eta);
+
+            let pdf = if sample.lobe.is_delta() {
+                (1.0 - p_coat) * sample.pdf
+            } else {
+                self.pdf(hit, -r_in.direction.unit(), wi)
+            };
+
+            Some(BsdfSample {
+                scatter: Scatter {
+                    attenuation: transmitted * sample.scatter.attenuation,
+                    ray: sample.scatter.ray,
+                },
+                pdf,
+                lobe: sample.lobe,
+            })
+        }
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        if !hit.front_face {
+            return self.base.eval(hit, wo, wi);
+        }
+
+        let eta = 1.0 / self.refractive_index;
+        let frame = Frame::from_normal(hit.shading_normal);
+        let (wo_local, wi_local) = (frame.to_local(wo), frame.to_local(wi));
+
+        let coat = if reflects(hit, wo, wi) {
+            self.coat_eval_pdf(hit, wo_local, wi_local).0
+        } else {
+            0.0
+        };
+        let transmitted = (1.0 - fresnel_dielectric(wo_local.z(), eta))
+            * (1.0 - fresnel_dielectric(wi_local.z(), eta));
+
+        coat * Vec3(1.0, 1.0, 1.0) + transmitted * self.base.eval(hit, wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        if !hit.front_face {
+            return self.base.pdf(hit, wo, wi);
+        }
+
+        let frame = Frame::from_normal(hit.shading_normal);
+        let wo_local = frame.to_local(wo);
+        let p_coat = fresnel_dielectric(wo_local.z(), 1.0 / self.refractive_index);
+        let coat = if Vec3::dot(wi, hit.normal) > 0.0 {
+            self.coat_eval_pdf(hit, wo_local, frame.to_local(wi)).1
+        } else {
+            0.0
+        };
+
+        p_coat * coat + (1.0 - p_coat) * self.base.pdf(hit, wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.base.flags() | BsdfFlags::GLOSSY
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.base.albedo(hit)
+    }
+}
+
+// Translucent material for skin, wax and marble. Light refracts through a smooth interface and
+// then random walks through a scattering interior, so it only makes sense on closed surfaces.
+pub struct Subsurface {
+    interface: Dielectric,
+    medium: Homogeneous,
+}
+
+impl Subsurface {
+    // Albedo is the chance of scattering rather than being absorbed at each step of the walk, and
+    // mean_free_path the average distance between steps, per channel
+    pub fn new(albedo: Vec3, mean_free_path: Vec3, refractive_index: f64) -> Subsurface {
+        let sigma_t = Vec3(
+            1.0 / mean_free_path.x(),
+            1.0 / mean_free_path.y(),
+            1.0 / mean_free_path.z(),
+        );
+        let sigma_s = albedo * sigma_t;
+
+        Subsurface {
+            interface: Dielectric {
+                refractive_index,
+                absorption: Vec3(0.0, 0.0, 0.0),
+                dispersion: None,
+                thin_film: None,
+            },
+            medium: Homogeneous {
+                sigma_a: sigma_t - sigma_s,
+                sigma_s,
+                phase: HenyeyGreenstein::ISOTROPIC,
+            },
         }
+    }
+}
+
+impl Material for Subsurface {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        self.interface.sample(r_in, hit)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.interface.flags()
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Vec3 {
+        let sigma_t = self.medium.sigma_a + self.medium.sigma_s;
+        Vec3(
+            self.medium.sigma_s.x() / sigma_t.x(),
+            self.medium.sigma_s.y() / sigma_t.y(),
+            self.medium.sigma_s.z() / sigma_t.z(),
+        )
+    }
+
+    fn interior(&self) -> Option<&dyn Medium> {
+        Some(&self.medium)
+    }
+}
+
+// Area light. Glows the same from both sides, and absorbs everything that hits it.
+pub struct DiffuseLight {
+    pub emit: Box<dyn Texture>,
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _r_in: &Ray, _hit: &Hit) -> Option<BsdfSample> {
+        None
+    }
 
-        let mut rng = rand::thread_rng();
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.emit.value(hit.u, hit.v, hit.point)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        self.emit.value(hit.u, hit.v, hit.point)
+    }
+}
 
-        if rng.gen::<f64>() < Dielectric::schlick(cos_theta, etai_over_etat) {
-            return reflect_scatter;
+// Invisible surface marking the boundary of a medium, for fog and smoke. Rays carry straight on
+// through it, entering or leaving the medium as they go.
+pub struct MediumInterface {
+    pub medium: Box<dyn Medium>,
+}
+
+impl Material for MediumInterface {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: Vec3(1.0, 1.0, 1.0),
+                ray: Ray {
+                    origin: hit.point,
+                    direction: r_in.direction,
+                    wavelength: r_in.wavelength,
+                    time: r_in.time,
+                },
+            },
+            pdf: 1.0,
+            lobe: BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION | BsdfFlags::PASS_THROUGH,
+        })
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION | BsdfFlags::PASS_THROUGH
+    }
+
+    fn interior(&self) -> Option<&dyn Medium> {
+        Some(self.medium.as_ref())
+    }
+
+    fn pass_through(&self, _hit: &Hit) -> f64 {
+        1.0
+    }
+}
+
+// Blend of two materials, picking b with the probability given by the weight texture's first
+// channel. Evaluable lobes are combined so light sampling sees the mixture as one BSDF.
+pub struct Mix {
+    pub a: Box<dyn Material>,
+    pub b: Box<dyn Material>,
+    pub weight: Box<dyn Texture>,
+}
+
+impl Mix {
+    fn weight(&self, hit: &Hit) -> f64 {
+        num::clamp(self.weight.value(hit.u, hit.v, hit.point).x(), 0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let weight = self.weight(hit);
+
+        let mut rng = random::rng();
+        let (chosen, other, p_chosen) = if rng.gen::<f64>() < weight {
+            (&self.b, &self.a, weight)
+        } else {
+            (&self.a, &self.b, 1.0 - weight)
+        };
+
+        // Delta lobes can't be weighed against the other material, the selection probability
+        // already accounts for them
+        let sample = chosen.sample(r_in, hit)?;
+        if sample.lobe.is_delta() {
+            return Some(sample);
         }
 
-        let refracted = refract(unit_direction, hit.normal, etai_over_etat);
-        Some(Scatter {
-            attenuation,
-            ray: Ray {
-                origin: hit.point,
-                direction: refracted,
+        let wo = -r_in.direction.unit();
+        let wi = sample.scatter.ray.direction.unit();
+        let pdf = p_chosen * sample.pdf + (1.0 - p_chosen) * other.pdf(hit, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let cosine = Vec3::dot(wi, hit.shading_normal).abs();
+        Some(BsdfSample {
+            scatter: Scatter {
+                attenuation: (cosine / pdf) * self.eval(hit, wo, wi),
+                ray: sample.scatter.ray,
             },
+            pdf,
+            lobe: sample.lobe,
+        })
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.eval(hit, wo, wi) + weight * self.b.eval(hit, wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.pdf(hit, wo, wi) + weight * self.b.pdf(hit, wo, wi)
+    }
+
+    fn pass_through(&self, hit: &Hit) -> f64 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.pass_through(hit) + weight * self.b.pass_through(hit)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.emitted(hit) + weight * self.b.emitted(hit)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.a.flags() | self.b.flags()
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.albedo(hit) + weight * self.b.albedo(hit)
+    }
+}
+
+// Alpha mask over another material. Where the alpha texture's first channel is below one, rays
+// have a chance of carrying straight on as if nothing was there, for leaves and fences.
+pub struct Cutout {
+    pub alpha: Box<dyn Texture>,
+    pub material: Box<dyn Material>,
+}
+
+impl Cutout {
+    fn alpha(&self, hit: &Hit) -> f64 {
+        num::clamp(self.alpha.value(hit.u, hit.v, hit.point).x(), 0.0, 1.0)
+    }
+}
+
+impl Material for Cutout {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let alpha = self.alpha(hit);
+
+        let mut rng = random::rng();
+        if rng.gen::<f64>() >= alpha {
+            return Some(BsdfSample {
+                scatter: Scatter {
+                    attenuation: Vec3(1.0, 1.0, 1.0),
+                    ray: Ray {
+                        origin: hit.point,
+                        direction: r_in.direction,
+                        wavelength: r_in.wavelength,
+                        time: r_in.time,
+                    },
+                },
+                pdf: 1.0,
+                lobe: BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION | BsdfFlags::PASS_THROUGH,
+            });
+        }
+
+        // The alpha scales the BSDF and the chance of sampling it equally, so the weight is
+        // unchanged and only the density needs adjusting
+        self.material.sample(r_in, hit).map(|sample| BsdfSample {
+            pdf: alpha * sample.pdf,
+            ..sample
         })
     }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.alpha(hit) * self.material.eval(hit, wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        self.alpha(hit) * self.material.pdf(hit, wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.material.flags()
+            | BsdfFlags::SPECULAR
+            | BsdfFlags::TRANSMISSION
+            | BsdfFlags::PASS_THROUGH
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.material.albedo(hit)
+    }
+
+    fn interior(&self) -> Option<&dyn Medium> {
+        self.material.interior()
+    }
+
+    fn pass_through(&self, hit: &Hit) -> f64 {
+        let alpha = self.alpha(hit);
+        1.0 - alpha + alpha * self.material.pass_through(hit)
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        self.alpha(hit) * self.material.emitted(hit)
+    }
+}
+
+// Builds a shading normal from a direction given in the hit's tangent space, falling back to the
+// unperturbed normal when the result would face away from the geometry
+fn perturb_normal(hit: &Hit, local: Vec3) -> Vec3 {
+    let normal = hit.shading_normal;
+    let tangent = (hit.tangent - Vec3::dot(hit.tangent, normal) * normal).unit();
+    let bitangent = Vec3::cross(normal, tangent);
+
+    // Keep the bitangent pointing along +v on back faces as well
+    let bitangent = if Vec3::dot(bitangent, hit.bitangent) < 0.0 {
+        -bitangent
+    } else {
+        bitangent
+    };
+
+    let perturbed = (local.x() * tangent + local.y() * bitangent + local.z() * normal).unit();
+    if Vec3::dot(perturbed, hit.normal) > 0.0 {
+        perturbed
+    } else {
+        normal
+    }
+}
+
+// Wraps another material, replacing its shading normal with one read from a tangent space
+// normal map. Maps are expected to be linear (not sRGB), with +Z pointing out of the surface.
+pub struct NormalMap {
+    pub map: Box<dyn Texture>,
+    pub material: Box<dyn Material>,
+}
+
+impl NormalMap {
+    fn shade<'a>(&self, hit: &Hit<'a>) -> Hit<'a> {
+        let encoded = self.map.value(hit.u, hit.v, hit.point);
+        let local = 2.0 * encoded - Vec3(1.0, 1.0, 1.0);
+
+        Hit {
+            shading_normal: perturb_normal(hit, local),
+            ..*hit
+        }
+    }
+}
+
+impl Material for NormalMap {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        self.material.sample(r_in, &self.shade(hit))
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.material.eval(&self.shade(hit), wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        self.material.pdf(&self.shade(hit), wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.material.flags()
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.material.albedo(hit)
+    }
+
+    fn interior(&self) -> Option<&dyn Medium> {
+        self.material.interior()
+    }
+
+    fn pass_through(&self, hit: &Hit) -> f64 {
+        self.material.pass_through(&self.shade(hit))
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        self.material.emitted(hit)
+    }
+}
+
+// Wraps another material, tilting its shading normal by the slope of a scalar height map
+pub struct BumpMap {
+    pub height: Box<dyn Texture>,
+    pub scale: f64,
+    pub material: Box<dyn Material>,
+}
+
+impl BumpMap {
+    // Finite difference step, in UV and in world space for solid textures
+    const DELTA: f64 = 0.0005;
+
+    fn shade<'a>(&self, hit: &Hit<'a>) -> Hit<'a> {
+        let height = |u: f64, v: f64, point: Vec3| self.height.value(u, v, point).x();
+
+        let base = height(hit.u, hit.v, hit.point);
+        let du = (height(
+            hit.u + BumpMap::DELTA,
+            hit.v,
+            hit.point + BumpMap::DELTA * hit.tangent,
+        ) - base)
+            / BumpMap::DELTA;
+        let dv = (height(
+            hit.u,
+            hit.v + BumpMap::DELTA,
+            hit.point + BumpMap::DELTA * hit.bitangent,
+        ) - base)
+            / BumpMap::DELTA;
+
+        let local = Vec3(-self.scale * du, -self.scale * dv, 1.0);
+
+        Hit {
+            shading_normal: perturb_normal(hit, local),
+            ..*hit
+        }
+    }
+}
+
+impl Material for BumpMap {
+    fn sample(&self, r_in: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        self.material.sample(r_in, &self.shade(hit))
+    }
+
+    fn eval(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.material.eval(&self.shade(hit), wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: Vec3, wi: Vec3) -> f64 {
+        self.material.pdf(&self.shade(hit), wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.material.flags()
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vec3 {
+        self.material.albedo(hit)
+    }
+
+    fn interior(&self) -> Option<&dyn Medium> {
+        self.material.interior()
+    }
+
+    fn pass_through(&self, hit: &Hit) -> f64 {
+        self.material.pass_through(&self.shade(hit))
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        self.material.emitted(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::Wavelength;
+    use crate::vector::Mat4;
+
+    fn hit(material: &dyn Material) -> Hit {
+        Hit {
+            point: Vec3(0.0, 0.0, 0.0),
+            normal: Vec3(0.0, 0.0, 1.0),
+            shading_normal: Vec3(0.0, 0.0, 1.0),
+            tangent: Vec3(1.0, 0.0, 0.0),
+            bitangent: Vec3(0.0, 1.0, 0.0),
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            front_face: true,
+            material,
+            world_to_object: Mat4::IDENTITY,
+        }
+    }
+
+    fn materials() -> Vec<Box<dyn Material>> {
+        vec![
+            Box::new(Lambertian {
+                albedo: Vec3(0.5, 0.5, 0.5).into(),
+            }),
+            Box::new(OrenNayar {
+                albedo: Vec3(0.5, 0.5, 0.5).into(),
+                sigma: 20.0,
+            }),
+            Box::new(MetallicRoughness {
+                base_color: Vec3(0.8, 0.6, 0.4).into(),
+                metallic: 0.5,
+                roughness: 0.4,
+                metallic_roughness: None,
+            }),
+            Box::new(Metal {
+                albedo: Vec3(0.9, 0.8, 0.7).into(),
+                fuzz: 0.3.into(),
+                thin_film: None,
+            }),
+            Box::new(Coated {
+                refractive_index: 1.5,
+                roughness: 0.3.into(),
+                base: Box::new(Lambertian {
+                    albedo: Vec3(0.5, 0.5, 0.5).into(),
+                }),
+            }),
+            Box::new(RoughDielectric {
+                refractive_index: 1.5,
+                roughness: 0.4.into(),
+                absorption: Vec3(0.0, 0.0, 0.0),
+            }),
+        ]
+    }
+
+    // Light leaving at 30 degrees from the normal
+    fn wo() -> Vec3 {
+        Vec3(30f64.to_radians().sin(), 0.0, 30f64.to_radians().cos())
+    }
+
+    #[test]
+    fn test_flat_maps_keep_normal() {
+        let normal_map = NormalMap {
+            map: Vec3(0.5, 0.5, 1.0).into(),
+            material: materials().remove(0),
+        };
+        let shaded = normal_map.shade(&hit(&normal_map));
+        assert!((shaded.shading_normal - Vec3(0.0, 0.0, 1.0)).len() < 1e-12);
+
+        let bump_map = BumpMap {
+            height: 0.3.into(),
+            scale: 1.0,
+            material: materials().remove(0),
+        };
+        let shaded = bump_map.shade(&hit(&bump_map));
+        assert!((shaded.shading_normal - Vec3(0.0, 0.0, 1.0)).len() < 1e-12);
+
+        // Leaning along the tangent
+        let tilted = NormalMap {
+            map: Vec3(0.75, 0.5, 1.0).into(),
+            material: materials().remove(0),
+        };
+        let shaded = tilted.shade(&hit(&tilted));
+        assert!(shaded.shading_normal.x() > 0.0 && shaded.shading_normal.y().abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let (n_theta, n_phi) = (400, 800);
+        let (d_theta, d_phi) = (PI / n_theta as f64, 2.0 * PI / n_phi as f64);
+
+        for material in materials() {
+            let hit = hit(material.as_ref());
+            let mut total = 0.0;
+            for i in 0..n_theta {
+                let theta = (i as f64 + 0.5) * d_theta;
+                for j in 0..n_phi {
+                    let phi = (j as f64 + 0.5) * d_phi;
+                    let wi = Vec3(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    total += material.pdf(&hit, wo(), wi) * theta.sin() * d_theta * d_phi;
+                }
+            }
+            assert!((total - 1.0).abs() < 0.02, "pdf integrates to {}", total);
+        }
+    }
+
+    #[test]
+    fn test_sample_pdf_matches_pdf() {
+        for material in materials() {
+            let hit = hit(material.as_ref());
+            let r_in = Ray {
+                origin: wo(),
+                direction: -wo(),
+                wavelength: Wavelength::random(),
+                time: 0.0,
+            };
+            for _ in 0..1000 {
+                if let Some(sample) = material.sample(&r_in, &hit) {
+                    let wi = sample.scatter.ray.direction.unit();
+                    let pdf = material.pdf(&hit, wo(), wi);
+                    assert!(
+                        (sample.pdf - pdf).abs() <= 1e-6 * pdf.max(1.0),
+                        "sampled {} but pdf {}",
+                        sample.pdf,
+                        pdf
+                    );
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,280 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use super::microfacet::Frame;
+use super::random;
+use super::vector::{Ray, Vec3};
+use super::voxel::VoxelGrid;
+
+// Henyey-Greenstein phase function. g runs from -1 (back scattering) through 0 (isotropic) to 1
+// (forward scattering).
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreenstein {
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub const ISOTROPIC: HenyeyGreenstein = HenyeyGreenstein { g: 0.0 };
+
+    // Density of scattering by an angle with the given cosine to the direction of travel
+    pub fn eval(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    // New direction for light travelling along direction. Sampling is exact, so the weight is one.
+    pub fn sample(&self, direction: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            (1.0 + g * g - term * term) / (2.0 * g)
+        };
+        let cos_theta = num::clamp(cos_theta, -1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        Frame::from_normal(direction.unit()).to_world(Vec3(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+// What happened to a ray travelling through a medium up to the next surface
+pub enum MediumEvent {
+    // Scattered at ray parameter t, with the path weight picked up on the way
+    Scatter { t: f64, weight: Vec3 },
+    // Reached the surface
+    Pass { weight: Vec3 },
+}
+
+// Participating media fill the inside of a closed surface, and are entered and left by
+// transmitting through it
+pub trait Medium: Send + Sync {
+    // Samples a free flight along the ray, up to the surface at t_max. Throughput is the path's
+    // weight so far, for media that need to choose which channel to follow.
+    fn sample(&self, r: &Ray, t_max: f64, throughput: Vec3) -> MediumEvent;
+
+    // Fraction of light making it along the ray up to t_max without being absorbed or scattered
+    fn transmittance(&self, r: &Ray, t_max: f64) -> Vec3;
+
+    fn phase(&self) -> HenyeyGreenstein;
+}
+
+// Medium with the same coefficients everywhere. Coefficients are per unit of distance and can
+// differ per channel, which makes the distance sampling pick a channel to follow.
+pub struct Homogeneous {
+    pub sigma_a: Vec3,
+    pub sigma_s: Vec3,
+    pub phase: HenyeyGreenstein,
+}
+
+impl Homogeneous {
+    fn attenuation(sigma_t: Vec3, distance: f64) -> Vec3 {
+        Vec3(
+            (-sigma_t.x() * distance).exp(),
+            (-sigma_t.y() * distance).exp(),
+            (-sigma_t.z() * distance).exp(),
+        )
+    }
+}
+
+// Chance of following each channel. Going by the path's throughput stops channels that have
+// already been attenuated away from being followed, which over a long random walk would
+// otherwise blow up the weights of the others.
+fn channel_probabilities(throughput: Vec3) -> Vec3 {
+    let sum = throughput.x() + throughput.y() + throughput.z();
+    if sum > 0.0 {
+        throughput / sum
+    } else {
+        Vec3(1.0, 1.0, 1.0) / 3.0
+    }
+}
+
+impl Medium for Homogeneous {
+    fn sample(&self, r: &Ray, t_max: f64, throughput: Vec3) -> MediumEvent {
+        let mut rng = random::rng();
+
+        let sigma_t = self.sigma_a + self.sigma_s;
+        let probabilities = channel_probabilities(throughput);
+
+        let u = rng.gen::<f64>();
+        let channel = if u < probabilities.x() {
+            sigma_t.x()
+        } else if u < probabilities.x() + probabilities.y() {
+            sigma_t.y()
+        } else {
+            sigma_t.z()
+        };
+
+        let speed = r.direction.len();
+        let max_distance = t_max * speed;
+        let distance = if channel > 0.0 {
+            -(1.0 - rng.gen::<f64>()).ln() / channel
+        } else {
+            f64::INFINITY
+        };
+
+        // The flight was sampled from one channel, so the weight divides by the density averaged
+        // over all three (one-sample MIS between the channels)
+        if distance < max_distance {
+            let transmittance = Homogeneous::attenuation(sigma_t, distance);
+            let pdf = Vec3::dot(probabilities, sigma_t * transmittance);
+            MediumEvent::Scatter {
+                t: distance / speed,
+                weight: self.sigma_s * transmittance / pdf,
+            }
+        } else {
+            let transmittance = Homogeneous::attenuation(sigma_t, max_distance);
+            let pdf = Vec3::dot(probabilities, transmittance);
+            MediumEvent::Pass {
+                weight: transmittance / pdf,
+            }
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_max: f64) -> Vec3 {
+        let distance = t_max * r.direction.len();
+        Homogeneous::attenuation(self.sigma_a + self.sigma_s, distance)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+// Medium with its density read from a voxel grid stretched over a box. Extinction is the same for
+// every channel, so the albedo alone colors it.
+pub struct GridMedium {
+    grid: VoxelGrid,
+    min: Vec3,
+    max: Vec3,
+    // Extinction per unit of distance where the grid's density is one
+    density: f64,
+    albedo: Vec3,
+    phase: HenyeyGreenstein,
+    // Bound on the extinction anywhere in the grid, for tracking against
+    majorant: f64,
+}
+
+impl GridMedium {
+    pub fn new(
+        grid: VoxelGrid,
+        min: Vec3,
+        max: Vec3,
+        density: f64,
+        albedo: Vec3,
+        phase: HenyeyGreenstein,
+    ) -> GridMedium {
+        let majorant = density * grid.max_density();
+        GridMedium {
+            grid,
+            min,
+            max,
+            density,
+            albedo,
+            phase,
+            majorant,
+        }
+    }
+
+    fn extinction(&self, point: Vec3) -> f64 {
+        let size = self.max - self.min;
+        let local = point - self.min;
+        self.density
+            * self.grid.density(Vec3(
+                local.x() / size.x(),
+                local.y() / size.y(),
+                local.z() / size.z(),
+            ))
+    }
+
+    // Visits tentative collisions along the ray, distributed as if the medium were filled to the
+    // majorant everywhere, until the callback returns false or the flight reaches t_max. Returns
+    // the parameter of the collision the callback stopped at.
+    fn track<F: FnMut(f64) -> bool>(&self, r: &Ray, t_max: f64, mut collide: F) -> Option<f64> {
+        // Rays that never reach a surface have lost track of the box, and would only ever find
+        // null collisions
+        if self.majorant <= 0.0 || !t_max.is_finite() {
+            return None;
+        }
+
+        let mut rng = random::rng();
+        let speed = r.direction.len();
+        let mut t = 0.0;
+
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (self.majorant * speed);
+            if t >= t_max {
+                return None;
+            }
+
+            if !collide(t) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Medium for GridMedium {
+    // Delta tracking (Woodcock tracking): a collision is real with the chance of the density over
+    // the majorant, and null otherwise, which makes the weight exactly the albedo
+    fn sample(&self, r: &Ray, t_max: f64, _throughput: Vec3) -> MediumEvent {
+        let mut rng = random::rng();
+        let collision = self.track(r, t_max, |t| {
+            rng.gen::<f64>() >= self.extinction(r.at(t)) / self.majorant
+        });
+
+        match collision {
+            Some(t) => MediumEvent::Scatter {
+                t,
+                weight: self.albedo,
+            },
+            _ => MediumEvent::Pass {
+                weight: Vec3(1.0, 1.0, 1.0),
+            },
+        }
+    }
+
+    // Ratio tracking: every tentative collision scales the estimate by its chance of being null,
+    // which is much less noisy than counting the rays that make it through
+    fn transmittance(&self, r: &Ray, t_max: f64) -> Vec3 {
+        let mut transmittance = 1.0;
+        self.track(r, t_max, |t| {
+            transmittance *= 1.0 - self.extinction(r.at(t)) / self.majorant;
+            transmittance > 0.0
+        });
+
+        Vec3(transmittance, transmittance, transmittance)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_henyey_greenstein_normalized() {
+        let count = 10000;
+        for &g in [-0.7, 0.0, 0.3, 0.9].iter() {
+            let phase = HenyeyGreenstein { g };
+            let integral: f64 = (0..count)
+                .map(|i| {
+                    let cos_theta = -1.0 + 2.0 * (i as f64 + 0.5) / count as f64;
+                    2.0 * PI * phase.eval(cos_theta) * 2.0 / count as f64
+                })
+                .sum();
+
+            assert!((integral - 1.0).abs() < 1e-3);
+        }
+    }
+}
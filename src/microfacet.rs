@@ -0,0 +1,141 @@
+use std::f64::consts::PI;
+
+use super::vector::Vec3;
+
+// Orthonormal basis with the normal as +Z, for working with BSDFs in local coordinates
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    // Duff et al., "Building an Orthonormal Basis, Revisited"
+    pub fn from_normal(n: Vec3) -> Frame {
+        let sign = 1.0f64.copysign(n.z());
+        let a = -1.0 / (sign + n.z());
+        let b = n.x() * n.y() * a;
+
+        Frame {
+            s: Vec3(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+            t: Vec3(b, sign + n.y() * n.y() * a, -n.y()),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3(
+            Vec3::dot(v, self.s),
+            Vec3::dot(v, self.t),
+            Vec3::dot(v, self.n),
+        )
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.s + v.y() * self.t + v.z() * self.n
+    }
+}
+
+// Cosine weighted direction around +Z, with pdf cos(theta) / pi
+pub fn sample_cosine_hemisphere(u1: f64, u2: f64) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+
+    Vec3(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+pub fn fresnel_schlick(f0: Vec3, cosine: f64) -> Vec3 {
+    let weight = (1.0 - cosine).max(0.0).powi(5);
+    f0 + weight * (Vec3(1.0, 1.0, 1.0) - f0)
+}
+
+// Unpolarized Fresnel reflectance for light crossing from a medium with relative index
+// eta = eta_incident / eta_transmitted. Returns 1 under total internal reflection.
+pub fn fresnel_dielectric(cos_incident: f64, eta: f64) -> f64 {
+    let cos_i = cos_incident.abs().min(1.0);
+    let sin_t_sqr = eta * eta * (1.0 - cos_i * cos_i);
+    if sin_t_sqr >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin_t_sqr).sqrt();
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals. All directions are in the local
+// frame of the surface, pointing away from it.
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    // Perfectly smooth surfaces break the distribution, so alpha is kept just above zero
+    pub fn from_roughness(roughness: f64) -> Ggx {
+        Ggx {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let alpha_sqr = self.alpha * self.alpha;
+        let denominator = m.z() * m.z() * (alpha_sqr - 1.0) + 1.0;
+        alpha_sqr / (PI * denominator * denominator)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos_sqr = w.z() * w.z();
+        if cos_sqr <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        let tan_sqr = (1.0 - cos_sqr).max(0.0) / cos_sqr;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan_sqr).sqrt())
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking-shadowing
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Heitz, "Sampling the GGX Distribution of Visible Normals"
+    pub fn sample_visible(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let vh = Vec3(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit();
+
+        let len_sqr = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len_sqr > 0.0 {
+            Vec3(-vh.y(), vh.x(), 0.0) / len_sqr.sqrt()
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vec3(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)).unit()
+    }
+
+    // Density of sample_visible returning m
+    pub fn pdf_visible(&self, wo: Vec3, m: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * Vec3::dot(wo, m).max(0.0) * self.d(m) / wo.z()
+    }
+}
@@ -1,5 +1,8 @@
-use super::material::Material;
-use super::vector::{Ray, Vec3};
+use super::material::{Material, MediumInterface};
+use super::medium::{GridMedium, HenyeyGreenstein, Homogeneous};
+use super::microfacet::Frame;
+use super::vector::{Mat4, Normal3, Point3, Quat, Ray, Vec3};
+use super::voxel::VoxelGrid;
 use std::sync::Arc;
 
 // Minimum t to reduce acne
@@ -8,10 +11,21 @@ pub(crate) const T_MIN: f64 = 0.0001;
 #[derive(Clone, Copy)]
 pub struct Hit<'a> {
     pub point: Vec3,
+    // Geometric normal, flipped to face the incoming ray
     pub normal: Vec3,
+    // Normal used for shading, which normal and bump maps are free to perturb
+    pub shading_normal: Vec3,
+    // Surface directions of increasing u and v
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
     pub material: &'a dyn Material,
+    // Takes world space into the space the model was built in, for media that need to look up
+    // where they are. The identity outside of transforms.
+    pub world_to_object: Mat4,
 }
 
 pub fn get_face_normal(r: &Ray, outward_normal: Vec3) -> (bool, Vec3) {
@@ -25,8 +39,21 @@ pub fn get_face_normal(r: &Ray, outward_normal: Vec3) -> (bool, Vec3) {
     (front_face, normal)
 }
 
+// What a ray is being traced for, so models can choose which rays see them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RayType {
+    // From the camera, including on through anything it refracts through or passes
+    Camera,
+    // Towards a light, only checking whether something is in the way
+    Shadow,
+    // Off a mirror or glossy reflection
+    Reflection,
+    // Off a diffuse surface or out of a medium, gathering light from everything around
+    Diffuse,
+}
+
 pub trait Model: Send + Sync {
-    fn hit(&self, r: &Ray) -> Option<Hit>;
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit>;
 }
 
 pub struct Sphere {
@@ -35,8 +62,89 @@ pub struct Sphere {
     pub material: Box<dyn Material>,
 }
 
+impl Sphere {
+    // Spherical UVs for a point on the unit sphere, with v running from -Y up to +Y
+    fn get_uv(point: Vec3) -> (f64, f64) {
+        let theta = (-point.y()).acos();
+        let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+
+        (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        )
+    }
+
+    // Unit tangent and bitangent following the directions of increasing u and v
+    fn get_tangents(point: Vec3) -> (Vec3, Vec3) {
+        let tangent = Vec3(point.z(), 0.0, -point.x());
+
+        // The tangent vanishes at the poles, any horizontal direction will do there
+        let tangent = if tangent.len_sqr() > 1e-12 {
+            tangent.unit()
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+
+        (tangent, Vec3::cross(point, tangent).unit())
+    }
+}
+
 impl Model for Sphere {
-    fn hit(&self, r: &Ray) -> Option<Hit> {
+    fn hit(&self, r: &Ray, _ray_type: RayType) -> Option<Hit> {
+        Ball {
+            center: self.center,
+            radius: self.radius,
+        }
+        .hit(r, self.material.as_ref())
+    }
+}
+
+// Sphere moving in a straight line, from center0 at time0 to center1 at time1
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Box<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f64) -> Vec3 {
+        let t = motion_fraction(time, self.time0, self.time1);
+        self.center0 + t * (self.center1 - self.center0)
+    }
+}
+
+// How far through the motion from time0 to time1 a ray's time is. Held at the ends outside of the
+// interval, so moving models stay put before and after it.
+fn motion_fraction(time: f64, time0: f64, time1: f64) -> f64 {
+    if time1 > time0 {
+        num::clamp((time - time0) / (time1 - time0), 0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+impl Model for MovingSphere {
+    fn hit(&self, r: &Ray, _ray_type: RayType) -> Option<Hit> {
+        Ball {
+            center: self.center(r.time),
+            radius: self.radius,
+        }
+        .hit(r, self.material.as_ref())
+    }
+}
+
+// Geometry of a sphere where it is when the ray passes
+#[derive(Clone, Copy)]
+struct Ball {
+    center: Vec3,
+    radius: f64,
+}
+
+impl Ball {
+    fn hit<'a>(&self, r: &Ray, material: &'a dyn Material) -> Option<Hit<'a>> {
         let oc = r.origin - self.center;
         let a = r.direction.len_sqr();
         let hf_b = Vec3::dot(oc, r.direction);
@@ -49,39 +157,458 @@ impl Model for Sphere {
             let t = ((-hf_b - root) / a, (-hf_b + root) / a);
 
             return match t {
-                (x, _) if x >= T_MIN => {
-                    let normals = get_face_normal(r, (r.at(x) - self.center) / self.radius);
-                    Some(Hit {
-                        t: x,
-                        point: r.at(x),
-                        normal: normals.1,
-                        front_face: normals.0,
-                        material: self.material.as_ref(),
-                    })
-                }
-                (_, y) if y >= T_MIN => {
-                    let normals = get_face_normal(r, (r.at(y) - self.center) / self.radius);
-                    Some(Hit {
-                        t: y,
-                        point: r.at(y),
-                        normal: normals.1,
-                        front_face: normals.0,
-                        material: self.material.as_ref(),
-                    })
-                }
+                (x, _) if x >= T_MIN => Some(self.hit_at(r, x, material)),
+                (_, y) if y >= T_MIN => Some(self.hit_at(r, y, material)),
                 _ => None,
             };
         }
 
         None
     }
+
+    fn hit_at<'a>(&self, r: &Ray, t: f64, material: &'a dyn Material) -> Hit<'a> {
+        let point = r.at(t);
+        let outward_normal = (point - self.center) / self.radius;
+        let normals = get_face_normal(r, outward_normal);
+        let (u, v) = Sphere::get_uv(outward_normal);
+        let (tangent, bitangent) = Sphere::get_tangents(outward_normal);
+
+        Hit {
+            t,
+            point,
+            normal: normals.1,
+            shading_normal: normals.1,
+            tangent,
+            bitangent,
+            u,
+            v,
+            front_face: normals.0,
+            material,
+            world_to_object: Mat4::IDENTITY,
+        }
+    }
+}
+
+// Flat four sided shape spanned by two edges from a corner
+#[derive(Clone, Copy)]
+struct Parallelogram {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+}
+
+impl Parallelogram {
+    fn hit<'a>(&self, r: &Ray, material: &'a dyn Material) -> Option<Hit<'a>> {
+        let n = Vec3::cross(self.u, self.v);
+        let outward_normal = n.unit();
+
+        // Parallel rays never cross the plane
+        let denominator = Vec3::dot(outward_normal, r.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = Vec3::dot(outward_normal, self.q - r.origin) / denominator;
+        if t < T_MIN {
+            return None;
+        }
+
+        // Coordinates of the point along the edges, which double as UVs
+        let point = r.at(t);
+        let planar = point - self.q;
+        let w = n / n.len_sqr();
+        let u = Vec3::dot(w, Vec3::cross(planar, self.v));
+        let v = Vec3::dot(w, Vec3::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+
+        let (front_face, normal) = get_face_normal(r, outward_normal);
+
+        Some(Hit {
+            t,
+            point,
+            normal,
+            shading_normal: normal,
+            tangent: self.u.unit(),
+            bitangent: self.v.unit(),
+            u,
+            v,
+            front_face,
+            material,
+            world_to_object: Mat4::IDENTITY,
+        })
+    }
+}
+
+// Parallelogram from the corner q along the edges u and v, facing along u x v
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Box<dyn Material>,
+}
+
+impl Quad {
+    // Axis aligned rectangles at k on the remaining axis, facing along it
+    pub fn xy(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Box<dyn Material>) -> Quad {
+        Quad {
+            q: Vec3(x0, y0, k),
+            u: Vec3(x1 - x0, 0.0, 0.0),
+            v: Vec3(0.0, y1 - y0, 0.0),
+            material,
+        }
+    }
+
+    pub fn xz(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Box<dyn Material>) -> Quad {
+        Quad {
+            q: Vec3(x0, k, z0),
+            u: Vec3(0.0, 0.0, z1 - z0),
+            v: Vec3(x1 - x0, 0.0, 0.0),
+            material,
+        }
+    }
+
+    pub fn yz(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Box<dyn Material>) -> Quad {
+        Quad {
+            q: Vec3(k, y0, z0),
+            u: Vec3(0.0, y1 - y0, 0.0),
+            v: Vec3(0.0, 0.0, z1 - z0),
+            material,
+        }
+    }
+}
+
+impl Model for Quad {
+    fn hit(&self, r: &Ray, _ray_type: RayType) -> Option<Hit> {
+        let shape = Parallelogram {
+            q: self.q,
+            u: self.u,
+            v: self.v,
+        };
+        shape.hit(r, self.material.as_ref())
+    }
+}
+
+// Axis aligned box between two opposite corners, with all six faces facing out
+pub struct Cuboid {
+    faces: [Parallelogram; 6],
+    material: Box<dyn Material>,
+}
+
+impl Cuboid {
+    pub fn new(a: Vec3, b: Vec3, material: Box<dyn Material>) -> Cuboid {
+        let min = Vec3(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Vec3(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+        let dx = Vec3(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3(0.0, 0.0, max.z() - min.z());
+
+        let face = |q: Vec3, u: Vec3, v: Vec3| Parallelogram { q, u, v };
+
+        Cuboid {
+            faces: [
+                face(Vec3(min.x(), min.y(), max.z()), dx, dy),
+                face(Vec3(max.x(), min.y(), max.z()), -dz, dy),
+                face(Vec3(max.x(), min.y(), min.z()), -dx, dy),
+                face(min, dz, dy),
+                face(Vec3(min.x(), max.y(), max.z()), dx, -dz),
+                face(min, dx, dz),
+            ],
+            material,
+        }
+    }
+}
+
+impl Model for Cuboid {
+    fn hit(&self, r: &Ray, _ray_type: RayType) -> Option<Hit> {
+        self.faces
+            .iter()
+            .filter_map(|face| face.hit(r, self.material.as_ref()))
+            .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+    }
+}
+
+// Fog filling a closed boundary, which scatters light the same amount everywhere inside it
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Model>,
+    interface: MediumInterface,
+}
+
+impl ConstantMedium {
+    // Density is the chance of scattering per unit of distance, and albedo the color of the fog
+    pub fn new(boundary: Box<dyn Model>, density: f64, albedo: Vec3) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            interface: MediumInterface {
+                medium: Box::new(Homogeneous {
+                    sigma_a: density * (Vec3(1.0, 1.0, 1.0) - albedo),
+                    sigma_s: density * albedo,
+                    phase: HenyeyGreenstein::ISOTROPIC,
+                }),
+            },
+        }
+    }
+}
+
+impl Model for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit> {
+        self.boundary.hit(r, ray_type).map(|hit| Hit {
+            material: &self.interface,
+            ..hit
+        })
+    }
+}
+
+// Smoke or cloud with its density given by a voxel grid, filling an axis aligned box
+pub struct GridVolume {
+    min: Vec3,
+    max: Vec3,
+    interface: MediumInterface,
+}
+
+impl GridVolume {
+    // Density scales the grid's values into extinction per unit of distance
+    pub fn new(
+        grid: VoxelGrid,
+        min: Vec3,
+        max: Vec3,
+        density: f64,
+        albedo: Vec3,
+        phase: HenyeyGreenstein,
+    ) -> GridVolume {
+        GridVolume {
+            min,
+            max,
+            interface: MediumInterface {
+                medium: Box::new(GridMedium::new(grid, min, max, density, albedo, phase)),
+            },
+        }
+    }
+}
+
+impl Model for GridVolume {
+    // Slab test, returning where the ray enters the box or, from inside, where it leaves
+    fn hit(&self, r: &Ray, _ray_type: RayType) -> Option<Hit> {
+        let origin: [f64; 3] = r.origin.into();
+        let direction: [f64; 3] = r.direction.into();
+        let min: [f64; 3] = self.min.into();
+        let max: [f64; 3] = self.max.into();
+
+        let (mut t_near, mut near_axis) = (f64::NEG_INFINITY, 0);
+        let (mut t_far, mut far_axis) = (f64::INFINITY, 0);
+
+        for axis in 0..3 {
+            let inverse = 1.0 / direction[axis];
+            let t0 = (min[axis] - origin[axis]) * inverse;
+            let t1 = (max[axis] - origin[axis]) * inverse;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
+            }
+        }
+
+        if t_near > t_far {
+            return None;
+        }
+
+        let (t, axis) = if t_near > T_MIN {
+            (t_near, near_axis)
+        } else if t_far > T_MIN {
+            (t_far, far_axis)
+        } else {
+            return None;
+        };
+
+        let point = r.at(t);
+        let coordinate: [f64; 3] = point.into();
+        let mut outward_normal = [0.0; 3];
+        outward_normal[axis] =
+            if (coordinate[axis] - min[axis]).abs() < (coordinate[axis] - max[axis]).abs() {
+                -1.0
+            } else {
+                1.0
+            };
+
+        let (front_face, normal) = get_face_normal(
+            r,
+            Vec3(outward_normal[0], outward_normal[1], outward_normal[2]),
+        );
+        let frame = Frame::from_normal(normal);
+
+        Some(Hit {
+            point,
+            normal,
+            shading_normal: normal,
+            tangent: frame.s,
+            bitangent: frame.t,
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face,
+            material: &self.interface,
+            world_to_object: Mat4::IDENTITY,
+        })
+    }
+}
+
+// Places a shared model in the world with an affine transform, so the same geometry can be
+// instanced many times
+pub struct Transform {
+    pub model: Arc<dyn Model>,
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    // None if the matrix can't be inverted, like a scale of zero along some axis
+    pub fn new(model: Arc<dyn Model>, matrix: Mat4) -> Option<Transform> {
+        Some(Transform {
+            model,
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+}
+
+impl Model for Transform {
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit> {
+        transform_hit(
+            self.model.as_ref(),
+            &self.matrix,
+            &self.inverse,
+            r,
+            ray_type,
+        )
+    }
+}
+
+fn transform_hit<'a>(
+    model: &'a dyn Model,
+    matrix: &Mat4,
+    inverse: &Mat4,
+    r: &Ray,
+    ray_type: RayType,
+) -> Option<Hit<'a>> {
+    // The direction isn't normalized, so t is the same in both spaces
+    let local = Ray {
+        origin: (*inverse * Point3(r.origin)).0,
+        direction: inverse.transform_vector(r.direction),
+        wavelength: r.wavelength,
+        time: r.time,
+    };
+
+    let hit = model.hit(&local, ray_type)?;
+    let normal = |n: Vec3| (Normal3(n) * *inverse).unit().0;
+
+    Some(Hit {
+        point: r.at(hit.t),
+        normal: normal(hit.normal),
+        shading_normal: normal(hit.shading_normal),
+        tangent: matrix.transform_vector(hit.tangent).unit(),
+        bitangent: matrix.transform_vector(hit.bitangent).unit(),
+        world_to_object: hit.world_to_object * *inverse,
+        ..hit
+    })
+}
+
+// Scale, then rotation, then translation. Unlike a matrix these can be interpolated without
+// shearing or shrinking the model part way through a rotation.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    fn lerp(a: &Keyframe, b: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            translation: a.translation + t * (b.translation - a.translation),
+            rotation: Quat::slerp(a.rotation, b.rotation, t),
+            scale: a.scale + t * (b.scale - a.scale),
+        }
+    }
+
+    fn matrix(&self) -> Mat4 {
+        Mat4::translation(self.translation) * Mat4::from(self.rotation) * Mat4::scale(self.scale)
+    }
+
+    // Each part is simple to undo on its own, which saves a general inverse on every ray
+    fn inverse(&self) -> Mat4 {
+        let scale = Vec3(
+            1.0 / self.scale.x(),
+            1.0 / self.scale.y(),
+            1.0 / self.scale.z(),
+        );
+        Mat4::scale(scale)
+            * Mat4::from(self.rotation.conjugate())
+            * Mat4::translation(-self.translation)
+    }
+}
+
+// Transform that moves between two keyframes over the shutter interval, for motion blur
+pub struct AnimatedTransform {
+    pub model: Arc<dyn Model>,
+    pub start: Keyframe,
+    pub end: Keyframe,
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl Model for AnimatedTransform {
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit> {
+        let t = motion_fraction(r.time, self.time0, self.time1);
+        let keyframe = Keyframe::lerp(&self.start, &self.end, t);
+
+        transform_hit(
+            self.model.as_ref(),
+            &keyframe.matrix(),
+            &keyframe.inverse(),
+            r,
+            ray_type,
+        )
+    }
+}
+
+// Hides a model from some kinds of ray, for example to keep a huge ground plane out of
+// reflections, or to stop a prop from casting shadows
+pub struct Visibility {
+    pub model: Box<dyn Model>,
+    pub camera: bool,
+    pub shadows: bool,
+    pub reflections: bool,
+}
+
+impl Model for Visibility {
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit> {
+        let visible = match ray_type {
+            RayType::Camera => self.camera,
+            RayType::Shadow => self.shadows,
+            RayType::Reflection => self.reflections,
+            RayType::Diffuse => true,
+        };
+
+        if visible {
+            self.model.hit(r, ray_type)
+        } else {
+            None
+        }
+    }
 }
 
 impl Model for Vec<Arc<dyn Model>> {
-    fn hit(&self, r: &Ray) -> Option<Hit> {
+    fn hit(&self, r: &Ray, ray_type: RayType) -> Option<Hit> {
         let mut closest_so_far: Option<Hit> = None;
         for item in self {
-            if let Some(hit) = item.hit(r) {
+            if let Some(hit) = item.hit(r, ray_type) {
                 match closest_so_far {
                     None => closest_so_far = Some(hit),
                     Some(old) => {
@@ -96,3 +623,180 @@ impl Model for Vec<Arc<dyn Model>> {
         closest_so_far
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::spectrum::Wavelength;
+
+    // Shared with the renderer's tests
+    pub(crate) fn gray() -> Box<dyn Material> {
+        Box::new(Lambertian {
+            albedo: Vec3(0.5, 0.5, 0.5).into(),
+        })
+    }
+
+    pub(crate) fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            wavelength: Wavelength::random(),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_sphere_tangents_follow_uv() {
+        for point in [
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 0.5, 1.0),
+            Vec3(-1.0, -0.5, 0.3),
+        ]
+        .iter()
+        {
+            let point = point.unit();
+            let (tangent, bitangent) = Sphere::get_tangents(point);
+            let (u, v) = Sphere::get_uv(point);
+
+            let step = |direction: Vec3| Sphere::get_uv((point + 1e-4 * direction).unit());
+            let (u_t, v_t) = step(tangent);
+            let (u_b, v_b) = step(bitangent);
+            assert!(u_t > u && (v_t - v).abs() < 1e-6);
+            assert!(v_b > v && (u_b - u).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_quad_hit() {
+        let quad = Quad::xy(0.0, 2.0, 0.0, 4.0, -1.0, gray());
+
+        let hit = quad
+            .hit(
+                &ray(Vec3(0.5, 1.0, 1.0), Vec3(0.0, 0.0, -1.0)),
+                RayType::Camera,
+            )
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!((hit.u - 0.25).abs() < 1e-9 && (hit.v - 0.25).abs() < 1e-9);
+        assert!(hit.front_face);
+
+        let miss = quad.hit(
+            &ray(Vec3(3.0, 1.0, 1.0), Vec3(0.0, 0.0, -1.0)),
+            RayType::Camera,
+        );
+        assert!(miss.is_none());
+    }
+
+    #[test]
+    fn test_cuboid_faces_outward() {
+        let cuboid = Cuboid::new(Vec3(-1.0, -1.0, -1.0), Vec3(1.0, 1.0, 1.0), gray());
+
+        let axes = [
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+        ];
+        for &axis in axes.iter() {
+            for &sign in [-1.0, 1.0].iter() {
+                let outside = 3.0 * sign * axis;
+                let hit = cuboid
+                    .hit(&ray(outside, -outside), RayType::Camera)
+                    .unwrap();
+
+                assert!(hit.front_face);
+                assert!((hit.point - sign * axis).len() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_motion_follows_ray_time() {
+        let moving = MovingSphere {
+            center0: Vec3(0.0, 0.0, 0.0),
+            center1: Vec3(0.0, 2.0, 0.0),
+            time0: 0.0,
+            time1: 1.0,
+            radius: 1.0,
+            material: gray(),
+        };
+        let animated = AnimatedTransform {
+            model: Arc::new(Sphere {
+                center: Vec3(0.0, 0.0, 0.0),
+                radius: 1.0,
+                material: gray(),
+            }),
+            start: Keyframe {
+                translation: Vec3(0.0, 0.0, 0.0),
+                rotation: Quat::IDENTITY,
+                scale: Vec3(1.0, 1.0, 1.0),
+            },
+            end: Keyframe {
+                translation: Vec3(0.0, 2.0, 0.0),
+                rotation: Quat::rotation(Vec3(0.0, 0.0, 1.0), 90.0),
+                scale: Vec3(1.0, 1.0, 1.0),
+            },
+            time0: 0.0,
+            time1: 1.0,
+        };
+
+        for model in [&moving as &dyn Model, &animated].iter() {
+            let at = |time: f64| {
+                let r = Ray {
+                    time,
+                    ..ray(Vec3(0.0, 0.5, -5.0), Vec3(0.0, 0.0, 1.0))
+                };
+                model.hit(&r, RayType::Camera).map(|hit| hit.t)
+            };
+
+            // Half a radius off center at the start and halfway through, then above the sphere
+            let expected = 5.0 - 0.75f64.sqrt();
+            assert!((at(0.0).unwrap() - expected).abs() < 1e-9);
+            assert!((at(0.5).unwrap() - expected).abs() < 1e-9);
+            assert!(at(1.0).is_none());
+
+            // Both hold still outside of the shutter interval
+            assert!((at(-1.0).unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_transform_maps_hits_to_object_space() {
+        let grid = VoxelGrid::from_fn(2, 2, 2, |_| 1.0);
+        let volume = GridVolume::new(
+            grid,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 1.0),
+            1.0,
+            Vec3(1.0, 1.0, 1.0),
+            HenyeyGreenstein::ISOTROPIC,
+        );
+        let instance = Transform::new(
+            Arc::new(Transform::new(Arc::new(volume), Mat4::scale(Vec3(2.0, 2.0, 2.0))).unwrap()),
+            Mat4::translation(Vec3(10.0, 0.0, 0.0)),
+        )
+        .unwrap();
+
+        let hit = instance
+            .hit(
+                &ray(Vec3(11.0, 1.0, -5.0), Vec3(0.0, 0.0, 1.0)),
+                RayType::Camera,
+            )
+            .unwrap();
+
+        // Through both transforms, back onto the front of the volume's own box
+        let local = (hit.world_to_object * Point3(hit.point)).0;
+        assert!((local - Vec3(0.5, 0.5, 0.0)).len() < 1e-9);
+    }
+
+    #[test]
+    fn test_singular_transform_rejected() {
+        let sphere = Sphere {
+            center: Vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: gray(),
+        };
+        let flat = Transform::new(Arc::new(sphere), Mat4::scale(Vec3(1.0, 0.0, 1.0)));
+        assert!(flat.is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use rand::seq::SliceRandom;
+
+use super::random;
+use super::vector::Vec3;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise, with random unit vectors on the lattice points so the result isn't blocky
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vec3::random(-1.0, 1.0).unit())
+            .collect();
+
+        Perlin {
+            gradients,
+            perm_x: Perlin::generate_perm(),
+            perm_y: Perlin::generate_perm(),
+            perm_z: Perlin::generate_perm(),
+        }
+    }
+
+    fn generate_perm() -> Vec<usize> {
+        let mut rng = random::rng();
+
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        perm.shuffle(&mut rng);
+        perm
+    }
+
+    // Smooth noise in roughly [-1, 1]
+    pub fn noise(&self, point: Vec3) -> f64 {
+        let u = point.x() - point.x().floor();
+        let v = point.y() - point.y().floor();
+        let w = point.z() - point.z().floor();
+
+        let i = point.x().floor() as i64;
+        let j = point.y().floor() as i64;
+        let k = point.z().floor() as i64;
+
+        let mut corners = [[[Vec3(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        Perlin::interpolate(&corners, u, v, w)
+    }
+
+    fn interpolate(corners: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        // Hermite smoothing hides the lattice
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accumulator = 0.0;
+        for (i, plane) in corners.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let (i, j, k) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3(u - i, v - j, w - k);
+
+                    accumulator += (i * uu + (1.0 - i) * (1.0 - uu))
+                        * (j * vv + (1.0 - j) * (1.0 - vv))
+                        * (k * ww + (1.0 - k) * (1.0 - ww))
+                        * Vec3::dot(*gradient, weight);
+                }
+            }
+        }
+
+        accumulator
+    }
+
+    // Sum of octaves of noise, each at double the frequency and half the amplitude
+    pub fn turbulence(&self, point: Vec3, depth: usize) -> f64 {
+        let mut accumulator = 0.0;
+        let mut temp_p = point;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accumulator += weight * self.noise(temp_p);
+            weight *= 0.5;
+            temp_p = 2.0 * temp_p;
+        }
+
+        accumulator.abs()
+    }
+}
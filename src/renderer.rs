@@ -1,130 +1,684 @@
 use rand::prelude::*;
 use rayon::prelude::*;
 
-use super::{camera::Camera, models::Model, vector::Ray, vector::Vec3};
+use super::{
+    camera::Camera,
+    denoise::JointBilateral,
+    light::{Light, LightSample},
+    material::BsdfFlags,
+    medium::{Medium, MediumEvent},
+    models::{Model, RayType},
+    random,
+    sky::Sky,
+    spectrum::Wavelength,
+    vector::{Mat4, Point3, Ray, Vec3},
+};
 use std::sync::*;
 
 use indicatif::ProgressBar;
 
+// Scattering events allowed inside media per path, on top of the surface bounces. Random walks
+// through dense media take a lot of small steps.
+const MAX_MEDIUM_EVENTS: usize = 1024;
+
+// Surfaces a shadow ray can pass through before giving up and calling it blocked
+const MAX_SHADOW_SURFACES: usize = 64;
+
+// Medium a path is inside of, with the transform into the space it was built in
+#[derive(Clone, Copy)]
+struct Inside<'a> {
+    medium: &'a dyn Medium,
+    world_to_object: Mat4,
+}
+
+impl<'a> Inside<'a> {
+    // Affine transforms keep the ray parameter, so ts along the local ray are valid in the world
+    fn local(&self, r: &Ray) -> Ray {
+        Ray {
+            origin: (self.world_to_object * Point3(r.origin)).0,
+            direction: self.world_to_object.transform_vector(r.direction),
+            wavelength: r.wavelength,
+            time: r.time,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Pixel(pub u8, pub u8, pub u8);
 
-fn ray_color(r: Ray, world: &dyn Model, depth: usize) -> Vec3 {
+// Linear radiance for a pixel, averaged over its samples, plus the first-hit feature buffers
+#[derive(Copy, Clone)]
+pub struct Sample {
+    pub color: Vec3,
+    pub albedo: Vec3,
+    pub normal: Vec3,
+}
+
+// Everything the renderer needs to know about what it's rendering, besides the camera
+pub struct Scene {
+    pub world: Box<dyn Model>,
+    pub sky: Sky,
+    // Lights sampled directly, on top of the sky's own, like the sun
+    pub lights: Vec<Box<dyn Light>>,
+}
+
+impl Scene {
+    fn all_lights(&self) -> impl Iterator<Item = &dyn Light> {
+        self.lights
+            .iter()
+            .map(|light| light.as_ref())
+            .chain(self.sky.light())
+    }
+}
+
+fn ray_color(r: Ray, scene: &Scene, depth: usize) -> Vec3 {
     if depth == 0 {
         return Vec3(0.0, 0.0, 0.0);
     }
 
-    match world.hit(&r) {
+    match scene.world.hit(&r, RayType::Reflection) {
         Some(hit) => match hit.material.scatter(&r, &hit) {
-            Some(scatter) => scatter.attenuation * ray_color(scatter.ray, world, depth - 1),
+            Some(scatter) => scatter.attenuation * ray_color(scatter.ray, scene, depth - 1),
             _ => Vec3(0.0, 0.0, 0.0),
         },
-        _ => {
-            let unit_direction = r.direction.unit();
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            (1.0 - t) * Vec3(1.0, 1.0, 1.0) + t * Vec3(0.5, 0.7, 1.0)
+        _ => scene.sky.radiance(r.direction),
+    }
+}
+
+// Whether two media are the same object, ignoring which vtable they were reached through
+fn same_medium(a: &dyn Medium, b: &dyn Medium) -> bool {
+    std::ptr::eq(
+        a as *const dyn Medium as *const u8,
+        b as *const dyn Medium as *const u8,
+    )
+}
+
+// Fraction of a light sample's irradiance that reaches the origin. Shadow rays see through
+// surfaces that let rays pass, like cutouts and medium boundaries, and get attenuated by the
+// media they cross on the way.
+fn shadow<'a>(
+    scene: &'a Scene,
+    origin: Vec3,
+    light: &dyn Light,
+    sample: &LightSample,
+    media: &[Inside<'a>],
+    wavelength: Wavelength,
+    time: f64,
+) -> Vec3 {
+    if !light.casts_shadows() {
+        return Vec3(1.0, 1.0, 1.0);
+    }
+
+    let mut media = media.to_vec();
+    let mut transmittance = Vec3(1.0, 1.0, 1.0);
+    let mut remaining = sample.distance;
+    let mut r = Ray {
+        origin,
+        direction: sample.direction,
+        wavelength,
+        time,
+    };
+
+    for _ in 0..MAX_SHADOW_SURFACES {
+        let hit = scene.world.hit(&r, RayType::Shadow);
+        let t_max = hit.as_ref().map_or(remaining, |hit| hit.t.min(remaining));
+
+        if let Some(inside) = media.last() {
+            transmittance = transmittance * inside.medium.transmittance(&inside.local(&r), t_max);
+        }
+
+        let hit = match hit {
+            Some(hit) if hit.t < remaining => hit,
+            _ => return transmittance,
+        };
+
+        // Leaving the medium the ray is in, like the inside of a subsurface object. Refraction
+        // would bend the ray on the way out, which shadow rays can't follow, so the boundary is
+        // taken as clear rather than leaving the inside unlit.
+        let leaving = !hit.front_face
+            && match (hit.material.interior(), media.last()) {
+                (Some(interior), Some(inside)) => same_medium(interior, inside.medium),
+                _ => false,
+            };
+
+        if !leaving {
+            let pass_through = hit.material.pass_through(&hit);
+            if pass_through <= 0.0 {
+                return Vec3(0.0, 0.0, 0.0);
+            }
+            transmittance = pass_through * transmittance;
         }
+
+        if let Some(interior) = hit.material.interior() {
+            if hit.front_face {
+                media.push(Inside {
+                    medium: interior,
+                    world_to_object: hit.world_to_object,
+                });
+            } else {
+                media.pop();
+            }
+        }
+
+        remaining -= hit.t;
+        r = Ray {
+            origin: hit.point,
+            direction: sample.direction,
+            wavelength,
+            time,
+        };
     }
+
+    Vec3(0.0, 0.0, 0.0)
 }
 
-fn ray_color_iter(r: Ray, world: &dyn Model, max_depth: usize) -> Vec3 {
+fn ray_color_iter(r: Ray, scene: &Scene, max_depth: usize) -> Vec3 {
+    // RGB throughput. Whatever depends on wavelength is in the ray's, which weights each bit of
+    // radiance as it's gathered.
     let mut color = Vec3(1.0, 1.0, 1.0);
+    let mut radiance = Vec3(0.0, 0.0, 0.0);
     let mut temp_r = r;
     let mut current_depth = max_depth as isize;
+    // Media the path is inside of, innermost last
+    let mut media: Vec<Inside> = Vec::new();
+    let mut medium_events = 0;
+    let mut ray_type = RayType::Camera;
+    // Whether the last bounce sampled the lights, so the sky shouldn't count them again
+    let mut sampled_lights = false;
 
     loop {
         if current_depth < 0 {
-            return Vec3(0.0, 0.0, 0.0);
+            return radiance;
+        }
+
+        let hit = scene.world.hit(&temp_r, ray_type);
+
+        if let Some(inside) = media.last() {
+            let t_max = hit.as_ref().map_or(f64::INFINITY, |hit| hit.t);
+
+            match inside.medium.sample(&inside.local(&temp_r), t_max, color) {
+                MediumEvent::Scatter { t, weight } => {
+                    medium_events += 1;
+                    if medium_events > MAX_MEDIUM_EVENTS {
+                        return radiance;
+                    }
+
+                    color = color * weight;
+                    let point = temp_r.at(t);
+                    let direction = temp_r.direction.unit();
+                    let phase = inside.medium.phase();
+
+                    // Direct lighting, weighted by the phase function in place of a BSDF
+                    for light in scene.all_lights() {
+                        if let Some(sample) = light.sample(point) {
+                            let scattering = phase.eval(Vec3::dot(direction, sample.direction));
+                            let visibility = shadow(
+                                scene,
+                                point,
+                                light,
+                                &sample,
+                                &media,
+                                temp_r.wavelength,
+                                temp_r.time,
+                            );
+                            radiance = radiance
+                                + scattering
+                                    * color
+                                    * temp_r.wavelength.weight()
+                                    * sample.irradiance
+                                    * visibility;
+                        }
+                    }
+
+                    let mut rng = random::rng();
+                    ray_type = RayType::Diffuse;
+                    sampled_lights = true;
+                    temp_r = Ray {
+                        origin: point,
+                        direction: phase.sample(direction, rng.gen(), rng.gen()),
+                        wavelength: temp_r.wavelength,
+                        time: temp_r.time,
+                    };
+                    continue;
+                }
+                MediumEvent::Pass { weight } => color = color * weight,
+            }
         }
 
-        match world.hit(&temp_r) {
+        match hit {
             // Hit an object in the world
-            Some(hit) => match hit.material.scatter(&temp_r, &hit) {
-                Some(scatter) => {
-                    color = color * scatter.attenuation;
-                    temp_r = scatter.ray;
-                    current_depth -= 1;
+            Some(hit) => {
+                radiance =
+                    radiance + color * temp_r.wavelength.weight() * hit.material.emitted(&hit);
+
+                // Direct lighting. Delta lobes can't be evaluated, and can't ever line up with a
+                // light that sits at a single point anyway.
+                if !hit.material.flags().is_delta() {
+                    let wo = -temp_r.direction.unit();
+                    for light in scene.all_lights() {
+                        if let Some(sample) = light.sample(hit.point) {
+                            let cosine = Vec3::dot(sample.direction, hit.shading_normal).abs();
+                            let bsdf = hit.material.eval(&hit, wo, sample.direction);
+                            let contribution = cosine * bsdf * sample.irradiance;
+                            if contribution.len_sqr() > 0.0 {
+                                let visibility = shadow(
+                                    scene,
+                                    hit.point,
+                                    light,
+                                    &sample,
+                                    &media,
+                                    temp_r.wavelength,
+                                    temp_r.time,
+                                );
+                                radiance = radiance
+                                    + color
+                                        * temp_r.wavelength.weight()
+                                        * contribution
+                                        * visibility;
+                            }
+                        }
+                    }
                 }
-                _ => {
-                    return Vec3(0.0, 0.0, 0.0);
+
+                match hit.material.sample(&temp_r, &hit) {
+                    Some(sample) => {
+                        let scatter = sample.scatter;
+
+                        // Transmitting through a surface enters or leaves the medium inside it
+                        if let Some(interior) = hit.material.interior() {
+                            if Vec3::dot(scatter.ray.direction, hit.normal) < 0.0 {
+                                if hit.front_face {
+                                    media.push(Inside {
+                                        medium: interior,
+                                        world_to_object: hit.world_to_object,
+                                    });
+                                } else {
+                                    media.pop();
+                                }
+                            }
+                        }
+
+                        color = color * scatter.attenuation;
+                        temp_r = scatter.ray;
+                        // Passing through fog boundaries and cutout holes changes nothing about
+                        // the path, and seeing through glass doesn't make a ray a reflection
+                        if !sample.lobe.contains(BsdfFlags::PASS_THROUGH) {
+                            sampled_lights = !sample.lobe.is_delta();
+                        }
+                        if sample.lobe.contains(BsdfFlags::DIFFUSE) {
+                            ray_type = RayType::Diffuse;
+                        } else if !sample.lobe.contains(BsdfFlags::TRANSMISSION) {
+                            ray_type = RayType::Reflection;
+                        }
+                        current_depth -= 1;
+                    }
+                    _ => {
+                        return radiance;
+                    }
                 }
-            },
+            }
             // Missed object
             _ => {
-                let unit_direction = temp_r.direction.unit();
-                let t = 0.5 * (unit_direction.y() + 1.0);
-                return color * ((1.0 - t) * Vec3(1.0, 1.0, 1.0) + t * Vec3(0.5, 0.7, 1.0));
+                let sky = if sampled_lights {
+                    scene.sky.unsampled_radiance(temp_r.direction)
+                } else {
+                    scene.sky.radiance(temp_r.direction)
+                };
+                return radiance + color * temp_r.wavelength.weight() * sky;
             }
         }
     }
 }
 
+// Albedo and normal of the first surface seen by a camera ray, for guiding the denoiser
+fn ray_features(r: &Ray, scene: &Scene) -> (Vec3, Vec3) {
+    match scene.world.hit(r, RayType::Camera) {
+        Some(hit) => (hit.material.albedo(&hit), hit.shading_normal),
+        _ => (scene.sky.radiance(r.direction), Vec3(0.0, 0.0, 0.0)),
+    }
+}
+
 fn core_render(
     camera: &Box<Camera>,
     height: usize,
     width: usize,
     samples: usize,
     j: usize,
-    world: &Box<dyn Model>,
-) -> Vec<Pixel> {
-    let mut rng = rand::thread_rng();
+    scene: &Scene,
+    features: bool,
+) -> Vec<Sample> {
+    let mut rng = random::rng();
 
     let mut line = Vec::with_capacity(width);
     for i in 0..width {
         let mut pixel_color = Vec3(0.0, 0.0, 0.0);
+        let mut pixel_albedo = Vec3(0.0, 0.0, 0.0);
+        let mut pixel_normal = Vec3(0.0, 0.0, 0.0);
 
-        for _ in 0..samples {
+        for s in 0..samples {
             let u = (i as f64 + rng.gen::<f64>()) / (width - 1) as f64;
             let v = (j as f64 + rng.gen::<f64>()) / (height - 1) as f64;
-            let r = camera.get_ray(u, v);
-            pixel_color = pixel_color + ray_color_iter(r, world.as_ref(), 50);
+            // Stratify wavelengths across the pixel to keep dispersion's color noise down
+            let r = camera.get_ray(u, v, Wavelength::stratified(s, samples, rng.gen()));
+            if features {
+                let (albedo, normal) = ray_features(&r, scene);
+                pixel_albedo = pixel_albedo + albedo;
+                pixel_normal = pixel_normal + normal;
+            }
+            pixel_color = pixel_color + ray_color_iter(r, scene, 50);
         }
 
-        let pixel = Pixel(
-            pixel_color.r(samples),
-            pixel_color.g(samples),
-            pixel_color.b(samples),
-        );
-        line.push(pixel);
+        let scale = 1.0 / samples as f64;
+        line.push(Sample {
+            color: scale * pixel_color,
+            albedo: scale * pixel_albedo,
+            normal: scale * pixel_normal,
+        });
     }
 
     return line;
 }
 
+// Tone maps the float framebuffer, running the denoiser on it first if one was requested
+fn finish(
+    frame: Vec<Vec<Sample>>,
+    denoiser: Option<JointBilateral>,
+) -> Arc<Mutex<Vec<Vec<Pixel>>>> {
+    let colors: Vec<Vec<Vec3>> = match denoiser {
+        Some(denoiser) => denoiser.denoise(&frame),
+        _ => frame
+            .iter()
+            .map(|line| line.iter().map(|sample| sample.color).collect())
+            .collect(),
+    };
+
+    let image = colors
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|color| Pixel(color.r(1), color.g(1), color.b(1)))
+                .collect()
+        })
+        .collect();
+
+    Arc::new(Mutex::new(image))
+}
+
 pub fn render(
-    world: Box<dyn Model>,
+    scene: Scene,
     camera: Box<Camera>,
     width: usize,
     height: usize,
     samples: usize,
+    denoiser: Option<JointBilateral>,
+    seed: Option<u64>,
 ) -> Arc<Mutex<Vec<Vec<Pixel>>>> {
-    let frame = Arc::new(Mutex::new(vec![Vec::with_capacity(5); height]));
+    let mut frame = vec![Vec::with_capacity(5); height];
+    let features = denoiser.is_some();
 
     for j in 0..height {
-        let line = core_render(&camera, height, width, samples, j, &world);
-        frame.lock().unwrap()[height - j - 1] = line;
+        if let Some(seed) = seed {
+            random::reseed(seed.wrapping_add(j as u64));
+        }
+        let line = core_render(&camera, height, width, samples, j, &scene, features);
+        frame[height - j - 1] = line;
         eprintln!("Done rendering line {0}", j);
     }
 
-    frame
+    finish(frame, denoiser)
 }
 
 pub fn render_par(
-    world: Box<dyn Model>,
+    scene: Scene,
     camera: Box<Camera>,
     width: usize,
     height: usize,
     samples: usize,
+    denoiser: Option<JointBilateral>,
+    seed: Option<u64>,
 ) -> Arc<Mutex<Vec<Vec<Pixel>>>> {
     let frame = Arc::new(Mutex::new(vec![Vec::with_capacity(1); height]));
+    let features = denoiser.is_some();
 
     let progress_bar = Arc::new(Mutex::new(ProgressBar::new(height as u64)));
 
     (0..height).into_par_iter().for_each(|j| {
-        let line = core_render(&camera, height, width, samples, j, &world);
+        // Seeding per line keeps renders repeatable whichever thread picks the line up
+        if let Some(seed) = seed {
+            random::reseed(seed.wrapping_add(j as u64));
+        }
+        let line = core_render(&camera, height, width, samples, j, &scene, features);
         progress_bar.lock().unwrap().inc(1);
         frame.lock().unwrap()[height - j - 1] = line;
     });
 
-    frame
+    let frame = Arc::try_unwrap(frame).ok().unwrap().into_inner().unwrap();
+    finish(frame, denoiser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::PointLight;
+    use crate::material::{
+        Cutout, Dielectric, DiffuseLight, Lambertian, Material, Metal, Subsurface,
+    };
+    use crate::models::tests::{gray, ray};
+    use crate::models::{ConstantMedium, Cuboid, Quad, Sphere, Visibility};
+
+    // Glowing wall behind a box of perfectly clear fog, seen only by the given kinds of ray
+    fn fog_scene(camera: bool, reflections: bool) -> Scene {
+        let fog = ConstantMedium::new(
+            Box::new(Cuboid::new(
+                Vec3(-1.0, -1.0, -1.0),
+                Vec3(1.0, 1.0, 1.0),
+                // Replaced by the medium's boundary
+                gray(),
+            )),
+            0.0,
+            Vec3(1.0, 1.0, 1.0),
+        );
+        let wall = Visibility {
+            model: Box::new(Quad::xy(
+                -2.0,
+                2.0,
+                -2.0,
+                2.0,
+                5.0,
+                Box::new(DiffuseLight {
+                    emit: Vec3(1.0, 1.0, 1.0).into(),
+                }),
+            )),
+            camera,
+            shadows: true,
+            reflections,
+        };
+
+        let world: Vec<Arc<dyn Model>> = vec![Arc::new(fog), Arc::new(wall)];
+        Scene {
+            world: Box::new(world),
+            sky: Sky::Solid(Vec3(0.0, 0.0, 0.0)),
+            lights: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_camera_rays_stay_camera_rays_through_fog() {
+        let r = || ray(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+
+        let hidden = ray_color_iter(r(), &fog_scene(false, true), 8);
+        assert_eq!(hidden, Vec3(0.0, 0.0, 0.0));
+
+        let unreflected = ray_color_iter(r(), &fog_scene(true, false), 8);
+        assert_eq!(unreflected, Vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_reflections_flag_only_hides_reflections() {
+        // Glowing wall that reflections don't see, in front of the given surface
+        let scene = |material: Box<dyn Material>| {
+            let wall = Visibility {
+                model: Box::new(Quad::xy(
+                    -2.0,
+                    2.0,
+                    -2.0,
+                    2.0,
+                    5.0,
+                    Box::new(DiffuseLight {
+                        emit: Vec3(1.0, 1.0, 1.0).into(),
+                    }),
+                )),
+                camera: true,
+                shadows: true,
+                reflections: false,
+            };
+            let surface = Quad::xy(-2.0, 2.0, -2.0, 2.0, -1.0, material);
+
+            let world: Vec<Arc<dyn Model>> = vec![Arc::new(wall), Arc::new(surface)];
+            Scene {
+                world: Box::new(world),
+                sky: Sky::Solid(Vec3(0.0, 0.0, 0.0)),
+                lights: Vec::new(),
+            }
+        };
+
+        // Seen through glass that doesn't bend or reflect anything head on
+        let glass = scene(Box::new(Dielectric {
+            refractive_index: 1.0,
+            absorption: Vec3(0.0, 0.0, 0.0),
+            dispersion: None,
+            thin_film: None,
+        }));
+        let through = ray_color_iter(ray(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0)), &glass, 8);
+        assert_eq!(through, Vec3(1.0, 1.0, 1.0));
+
+        // Hidden from a mirror
+        let mirror = scene(Box::new(Metal {
+            albedo: Vec3(1.0, 1.0, 1.0).into(),
+            fuzz: 0.0.into(),
+            thin_film: None,
+        }));
+        let reflected = ray_color_iter(ray(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0)), &mirror, 8);
+        assert_eq!(reflected, Vec3(0.0, 0.0, 0.0));
+
+        // Still lighting what's around it
+        let diffuse = scene(gray());
+        let mut bounced = Vec3(0.0, 0.0, 0.0);
+        for _ in 0..64 {
+            bounced = bounced
+                + ray_color_iter(ray(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0)), &diffuse, 8);
+        }
+        assert!(bounced.y() > 0.0);
+    }
+
+    #[test]
+    fn test_shadow() {
+        let light = PointLight {
+            position: Vec3(0.0, 0.0, 10.0),
+            intensity: Vec3(1.0, 1.0, 1.0),
+            casts_shadows: true,
+        };
+        let sample = light.sample(Vec3(0.0, 0.0, 0.0)).unwrap();
+        let visibility = |world: &Vec<Arc<dyn Model>>, light: &PointLight| {
+            let scene = Scene {
+                world: Box::new(world.clone()),
+                sky: Sky::Solid(Vec3(0.0, 0.0, 0.0)),
+                lights: Vec::new(),
+            };
+            shadow(
+                &scene,
+                Vec3(0.0, 0.0, 0.0),
+                light,
+                &sample,
+                &[],
+                Wavelength::random(),
+                0.0,
+            )
+        };
+
+        // A cutout letting three quarters of the light through, then two units of fog
+        let cutout = Quad::xy(
+            -1.0,
+            1.0,
+            -1.0,
+            1.0,
+            2.0,
+            Box::new(Cutout {
+                alpha: Vec3(0.25, 0.25, 0.25).into(),
+                material: gray(),
+            }),
+        );
+        let fog = ConstantMedium::new(
+            Box::new(Cuboid::new(
+                Vec3(-1.0, -1.0, 4.0),
+                Vec3(1.0, 1.0, 6.0),
+                gray(),
+            )),
+            0.5,
+            Vec3(1.0, 1.0, 1.0),
+        );
+        let mut world: Vec<Arc<dyn Model>> = vec![Arc::new(cutout), Arc::new(fog)];
+
+        let expected = 0.75 * (-1.0f64).exp();
+        let through = visibility(&world, &light);
+        assert!((through - Vec3(expected, expected, expected)).len() < 1e-9);
+
+        // Anything opaque in the way blocks the light completely, unless it doesn't cast shadows
+        world.push(Arc::new(Sphere {
+            center: Vec3(0.0, 0.0, 8.0),
+            radius: 0.5,
+            material: gray(),
+        }));
+        assert_eq!(visibility(&world, &light), Vec3(0.0, 0.0, 0.0));
+
+        let unshadowed = PointLight {
+            casts_shadows: false,
+            ..light
+        };
+        assert_eq!(visibility(&world, &unshadowed), Vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_point_light_reaches_subsurface() {
+        random::reseed(1);
+
+        let mean = |material: Box<dyn Material>| {
+            let world: Vec<Arc<dyn Model>> = vec![Arc::new(Sphere {
+                center: Vec3(0.0, 0.0, 0.0),
+                radius: 1.0,
+                material,
+            })];
+            let scene = Scene {
+                world: Box::new(world),
+                sky: Sky::Solid(Vec3(0.0, 0.0, 0.0)),
+                lights: vec![Box::new(PointLight {
+                    position: Vec3(0.0, 5.0, -5.0),
+                    intensity: Vec3(50.0, 50.0, 50.0),
+                    casts_shadows: true,
+                })],
+            };
+
+            const SAMPLES: usize = 256;
+            let mut total = Vec3(0.0, 0.0, 0.0);
+            for _ in 0..SAMPLES {
+                total = total
+                    + ray_color_iter(ray(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0)), &scene, 16);
+            }
+            total.y() / SAMPLES as f64
+        };
+
+        let lambertian = mean(gray());
+        let subsurface = mean(Box::new(Subsurface::new(
+            Vec3(0.9, 0.9, 0.9),
+            Vec3(0.2, 0.2, 0.2),
+            1.3,
+        )));
+        assert!(lambertian > 0.1);
+        assert!(
+            subsurface > 0.2 * lambertian,
+            "{} against {}",
+            subsurface,
+            lambertian
+        );
+    }
 }
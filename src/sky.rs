@@ -0,0 +1,275 @@
+use std::f64::consts::PI;
+
+use super::light::{DirectionalLight, Light};
+use super::spectrum::xyz_to_rgb;
+use super::vector::Vec3;
+
+// Apparent angular radius of the sun, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65;
+
+// Luminance of the sky's zenith. There's no exposure control, so this keeps daylight at about the
+// brightness of the gradient sky.
+const ZENITH_LUMINANCE: f64 = 0.25;
+
+// Irradiance from the sun before the atmosphere attenuates it, relative to the luminance of the
+// zenith. Works out to the sun giving about five times the light of the sky on a clear day.
+const SUN_IRRADIANCE: f64 = 18.0 * ZENITH_LUMINANCE;
+
+// Representative wavelengths of the red, green and blue channels, in micrometers
+const CHANNEL_WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+// Radiance arriving from directions that don't hit anything
+pub enum Sky {
+    // Blue to white blend on the y axis
+    Gradient,
+    // Same everywhere, black for closed interiors
+    Solid(Vec3),
+    Preetham(Preetham),
+}
+
+impl Sky {
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Sky::Gradient => {
+                let unit_direction = direction.unit();
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * Vec3(1.0, 1.0, 1.0) + t * Vec3(0.5, 0.7, 1.0)
+            }
+            Sky::Solid(color) => *color,
+            Sky::Preetham(sky) => sky.radiance(direction),
+        }
+    }
+
+    // Part of the sky bright and small enough to be sampled as a light, which the renderer does
+    // alongside the scene's lights
+    pub fn light(&self) -> Option<&dyn Light> {
+        match self {
+            Sky::Preetham(sky) => Some(&sky.sun),
+            _ => None,
+        }
+    }
+
+    // Radiance for paths that have just sampled the lights, leaving out the sky's own light
+    pub fn unsampled_radiance(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Sky::Preetham(sky) => sky.sky_radiance(direction),
+            _ => self.radiance(direction),
+        }
+    }
+}
+
+// Perez et al.'s sky luminance distribution, relative to the zenith
+#[derive(Clone, Copy)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    fn new(turbidity: f64, coefficients: [[f64; 2]; 5]) -> Perez {
+        let mut values = [0.0; 5];
+        for (value, [slope, intercept]) in values.iter_mut().zip(coefficients.iter()) {
+            *value = slope * turbidity + intercept;
+        }
+
+        Perez(values)
+    }
+
+    fn eval(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.0;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+}
+
+// Cubic in the sun's zenith angle for each power of turbidity, for the zenith chromaticity
+fn zenith_chromaticity(turbidity: f64, theta_sun: f64, coefficients: [[f64; 4]; 3]) -> f64 {
+    let angles = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+
+    turbidities
+        .iter()
+        .zip(coefficients.iter())
+        .map(|(t, row)| {
+            t * row
+                .iter()
+                .zip(angles.iter())
+                .map(|(c, a)| c * a)
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+// Analytic daylight from Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight"
+pub struct Preetham {
+    sun_direction: Vec3,
+    // Distributions of luminance and chromaticity, each already divided by its zenith value
+    luminance: Perez,
+    x: Perez,
+    y: Perez,
+    zenith_x: f64,
+    zenith_y: f64,
+    normalization: [f64; 3],
+    sun_radiance: Vec3,
+    // The sun's disk as a light, which is far less noisy than finding the disk by chance
+    sun: DirectionalLight,
+}
+
+impl Preetham {
+    // The sun direction points towards the sun, with +Y up. Turbidity runs from about 2 for a
+    // clear sky to 10 for a hazy one.
+    pub fn new(sun_direction: Vec3, turbidity: f64) -> Preetham {
+        let sun_direction = sun_direction.unit();
+        // The model is only fit for the sun above the horizon
+        let theta_sun = sun_direction.y().max(0.0).acos();
+
+        let luminance = Perez::new(
+            turbidity,
+            [
+                [0.1787, -1.4630],
+                [-0.3554, 0.4275],
+                [-0.0227, 5.3251],
+                [0.1206, -2.5771],
+                [-0.0670, 0.3703],
+            ],
+        );
+        let x = Perez::new(
+            turbidity,
+            [
+                [-0.0193, -0.2592],
+                [-0.0665, 0.0008],
+                [-0.0004, 0.2125],
+                [-0.0641, -0.8989],
+                [-0.0033, 0.0452],
+            ],
+        );
+        let y = Perez::new(
+            turbidity,
+            [
+                [-0.0167, -0.2608],
+                [-0.0950, 0.0092],
+                [-0.0079, 0.2102],
+                [-0.0441, -1.6537],
+                [-0.0109, 0.0529],
+            ],
+        );
+
+        let zenith_x = zenith_chromaticity(
+            turbidity,
+            theta_sun,
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+        );
+        let zenith_y = zenith_chromaticity(
+            turbidity,
+            theta_sun,
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+        );
+
+        let normalization = [
+            luminance.eval(1.0, theta_sun),
+            x.eval(1.0, theta_sun),
+            y.eval(1.0, theta_sun),
+        ];
+
+        let sun_radiance = Preetham::sun_transmittance(theta_sun, turbidity) * SUN_IRRADIANCE
+            / Preetham::sun_solid_angle();
+
+        Preetham {
+            sun_direction,
+            luminance,
+            x,
+            y,
+            zenith_x,
+            zenith_y,
+            normalization,
+            sun_radiance,
+            sun: DirectionalLight {
+                direction: -sun_direction,
+                irradiance: sun_radiance * Preetham::sun_solid_angle(),
+                casts_shadows: true,
+            },
+        }
+    }
+
+    fn sun_solid_angle() -> f64 {
+        2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos())
+    }
+
+    // Rayleigh and aerosol extinction along the path sunlight takes through the atmosphere
+    fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Vec3 {
+        // Relative optical mass of air, which grows quickly towards the horizon
+        let degrees = theta_sun.to_degrees();
+        let mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+
+        let beta = 0.046_08 * turbidity - 0.045_86;
+        let channel = |lambda: f64| {
+            let rayleigh = (-0.008_735 * lambda.powf(-4.08) * mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+            rayleigh * aerosol
+        };
+
+        Vec3(
+            channel(CHANNEL_WAVELENGTHS[0]),
+            channel(CHANNEL_WAVELENGTHS[1]),
+            channel(CHANNEL_WAVELENGTHS[2]),
+        )
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let sun = if Vec3::dot(direction.unit(), self.sun_direction) > SUN_ANGULAR_RADIUS.cos() {
+            self.sun_radiance
+        } else {
+            Vec3(0.0, 0.0, 0.0)
+        };
+
+        self.sky_radiance(direction) + sun
+    }
+
+    // Radiance of the sky without the sun's disk
+    fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.unit();
+        let cos_gamma = num::clamp(Vec3::dot(direction, self.sun_direction), -1.0, 1.0);
+
+        // Below the horizon, carry on with the color at the horizon
+        let cos_theta = direction.y().max(0.001);
+        let gamma = cos_gamma.acos();
+
+        let luminance =
+            ZENITH_LUMINANCE * self.luminance.eval(cos_theta, gamma) / self.normalization[0];
+        let x = self.zenith_x * self.x.eval(cos_theta, gamma) / self.normalization[1];
+        let y = self.zenith_y * self.y.eval(cos_theta, gamma) / self.normalization[2];
+
+        let xyz = Vec3(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_rgb(xyz);
+
+        // Chromaticities outside the sRGB gamut can leave a channel slightly negative
+        Vec3(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sun_light() {
+        let sun_direction = Vec3(0.0, 1.0, 1.0).unit();
+        let sky = Sky::Preetham(Preetham::new(sun_direction, 3.0));
+        let disk = sky.radiance(sun_direction) - sky.unsampled_radiance(sun_direction);
+        assert!(disk.x() > 0.0 && disk.y() > 0.0 && disk.z() > 0.0);
+
+        // The light delivers what the disk does
+        let sample = sky.light().unwrap().sample(Vec3(0.0, 0.0, 0.0)).unwrap();
+        assert!((sample.direction - sun_direction).len() < 1e-12);
+        assert!((sample.irradiance - disk * Preetham::sun_solid_angle()).len() < 1e-12);
+
+        // Away from the sun it's all sky
+        let away = Vec3(1.0, 1.0, -1.0);
+        assert_eq!(sky.radiance(away), sky.unsampled_radiance(away));
+        assert!(Sky::Gradient.light().is_none());
+    }
+}
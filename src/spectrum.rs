@@ -0,0 +1,261 @@
+use rand::Rng;
+
+use super::random;
+use super::vector::Vec3;
+
+pub const WAVELENGTH_MIN: f64 = 380.0;
+pub const WAVELENGTH_MAX: f64 = 780.0;
+
+// Average of xyz_to_rgb(cie_xyz(lambda)) over the visible range, so an equal energy spectrum
+// comes out as (1, 1, 1) like white does everywhere else in the renderer
+const RGB_NORMALIZATION: Vec3 = Vec3(0.320_902_557, 0.253_845_202, 0.242_662_001);
+
+// Wavelengths carried by each path, the hero and its secondaries
+pub const WAVELENGTHS: usize = 4;
+
+// Wavelengths a path stands for, in nanometers, for hero wavelength sampling (Wilkie et al.,
+// "Hero Wavelength Spectral Sampling").
+//
+// The renderer works in RGB, which is exact as long as nothing depends on wavelength, so paths
+// start out ignoring theirs. The first thing that does makes the path spectral: from then on each
+// wavelength keeps its own throughput, and the density of the path had it been the hero, and the
+// balance heuristic folds them back into RGB. Reflections off dispersive glass go the same way
+// for every wavelength and keep them all, while refractions only follow the hero and drop the
+// secondaries.
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelength {
+    // The hero first, then the secondaries spaced evenly around the visible range from it
+    pub lambdas: [f64; WAVELENGTHS],
+    // Throughput and path density per wavelength, for spectral paths
+    spectrum: Option<([Vec3; WAVELENGTHS], [f64; WAVELENGTHS])>,
+}
+
+impl Wavelength {
+    pub fn random() -> Wavelength {
+        let mut rng = random::rng();
+        Wavelength::stratified(0, 1, rng.gen())
+    }
+
+    // Wavelengths for one of several samples in a pixel, with the heroes spread evenly over the
+    // visible range
+    pub fn stratified(sample: usize, samples: usize, jitter: f64) -> Wavelength {
+        let u = (sample as f64 + jitter) / samples as f64;
+
+        let mut lambdas = [0.0; WAVELENGTHS];
+        for (i, lambda) in lambdas.iter_mut().enumerate() {
+            let rotated = (u + i as f64 / WAVELENGTHS as f64).fract();
+            *lambda = WAVELENGTH_MIN + rotated * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+        }
+
+        Wavelength {
+            lambdas,
+            spectrum: None,
+        }
+    }
+
+    // The wavelength that picks directions where they depend on it
+    pub fn hero(&self) -> f64 {
+        self.lambdas[0]
+    }
+
+    pub fn is_spectral(&self) -> bool {
+        self.spectrum.is_some()
+    }
+
+    // Multiplies in each wavelength's throughput, and the chance of sampling the same event had
+    // that wavelength been the hero. Makes the path spectral.
+    pub fn scale<F: Fn(f64) -> (Vec3, f64)>(&mut self, f: F) {
+        let (mut throughput, mut density) = self
+            .spectrum
+            .unwrap_or(([Vec3(1.0, 1.0, 1.0); WAVELENGTHS], [1.0; WAVELENGTHS]));
+
+        for i in 0..WAVELENGTHS {
+            let (t, d) = f(self.lambdas[i]);
+            throughput[i] = throughput[i] * t;
+            density[i] *= d;
+        }
+
+        self.spectrum = Some((throughput, density));
+    }
+
+    // Drops the secondaries after the hero took a direction only it could have
+    pub fn follow_hero(&mut self) {
+        let (mut throughput, mut density) = self
+            .spectrum
+            .unwrap_or(([Vec3(1.0, 1.0, 1.0); WAVELENGTHS], [1.0; WAVELENGTHS]));
+
+        for i in 1..WAVELENGTHS {
+            throughput[i] = Vec3(0.0, 0.0, 0.0);
+            density[i] = 0.0;
+        }
+
+        self.spectrum = Some((throughput, density));
+    }
+
+    // RGB weight of the path's spectrum, white until it becomes spectral
+    pub fn weight(&self) -> Vec3 {
+        match &self.spectrum {
+            Some((throughput, density)) => {
+                let total: f64 = density.iter().sum();
+                if total <= 0.0 {
+                    return Vec3(0.0, 0.0, 0.0);
+                }
+
+                let mut sum = Vec3(0.0, 0.0, 0.0);
+                for (lambda, throughput) in self.lambdas.iter().zip(throughput.iter()) {
+                    sum = sum + *throughput * wavelength_to_rgb(*lambda);
+                }
+                sum / total
+            }
+            _ => Vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// Piecewise Gaussian from Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE
+// XYZ Color Matching Functions"
+fn gaussian(lambda: f64, mean: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if lambda < mean { sigma_low } else { sigma_high };
+    let x = (lambda - mean) / sigma;
+    (-0.5 * x * x).exp()
+}
+
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3(
+        1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// CIE XYZ to linear sRGB primaries
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3(
+        3.240_454_2 * xyz.x() - 1.537_138_5 * xyz.y() - 0.498_531_4 * xyz.z(),
+        -0.969_266_0 * xyz.x() + 1.876_010_8 * xyz.y() + 0.041_556_0 * xyz.z(),
+        0.055_643_4 * xyz.x() - 0.204_025_9 * xyz.y() + 1.057_225_2 * xyz.z(),
+    )
+}
+
+// RGB weight of a single wavelength sampled uniformly over the visible range. Averages to white
+// over the range, and can go negative for saturated colors outside the sRGB gamut.
+pub fn wavelength_to_rgb(lambda: f64) -> Vec3 {
+    let rgb = xyz_to_rgb(cie_xyz(lambda));
+    Vec3(
+        rgb.x() / RGB_NORMALIZATION.x(),
+        rgb.y() / RGB_NORMALIZATION.y(),
+        rgb.z() / RGB_NORMALIZATION.z(),
+    )
+}
+
+// Wavelength dependent refractive index, with wavelengths in nanometers
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion {
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b * lambda^2 / (lambda^2 - c)), lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let lambda_sqr = micrometers * micrometers;
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda_sqr,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b
+                    .iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * lambda_sqr / (lambda_sqr - c))
+                    .sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_white_normalization() {
+        let count = 4000;
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        for i in 0..count {
+            let wavelength = Wavelength::stratified(i, count, 0.5);
+            sum = sum + wavelength_to_rgb(wavelength.hero());
+        }
+        let mean = sum / count as f64;
+
+        assert!((mean - Vec3(1.0, 1.0, 1.0)).len() < 1e-3);
+    }
+
+    #[test]
+    fn test_secondaries_spread_around_hero() {
+        let wavelength = Wavelength::stratified(3, 4, 0.5);
+        assert!((wavelength.hero() - 730.0).abs() < 1e-9);
+
+        let mut lambdas = wavelength.lambdas;
+        lambdas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (lambda, expected) in lambdas.iter().zip([430.0, 530.0, 630.0, 730.0].iter()) {
+            assert!((lambda - expected).abs() < 1e-9);
+        }
+
+        assert_eq!(wavelength.weight(), Vec3(1.0, 1.0, 1.0));
+        let mut hero = wavelength;
+        hero.follow_hero();
+        assert!((hero.weight() - wavelength_to_rgb(730.0)).len() < 1e-12);
+    }
+
+    // Reflecting with a chance that depends on wavelength, as off dispersive glass, keeps every
+    // wavelength and has to come out the same on average as following each one on its own
+    #[test]
+    fn test_hero_reflection_unbiased() {
+        let reflectance =
+            |lambda: f64| (lambda - WAVELENGTH_MIN) / (WAVELENGTH_MAX - WAVELENGTH_MIN);
+
+        let count = 4000;
+        let mut hero = Vec3(0.0, 0.0, 0.0);
+        let mut single = Vec3(0.0, 0.0, 0.0);
+        for i in 0..count {
+            let mut wavelength = Wavelength::stratified(i, count, 0.5);
+            let chance = reflectance(wavelength.hero());
+            single = single + chance * wavelength_to_rgb(wavelength.hero());
+
+            wavelength.scale(|lambda| {
+                let r = reflectance(lambda);
+                (Vec3(r, r, r), r)
+            });
+            hero = hero + chance * wavelength.weight();
+        }
+
+        assert!((hero - single).len() / (count as f64) < 1e-3);
+    }
+
+    #[test]
+    fn test_sellmeier_bk7() {
+        // Sodium D line
+        let n = Dispersion::BK7.refractive_index(587.6);
+        assert!((n - 1.5168).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_diamond() {
+        let n = Dispersion::DIAMOND.refractive_index(589.0);
+        assert!((n - 2.417).abs() < 2e-3);
+    }
+}
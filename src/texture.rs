@@ -0,0 +1,261 @@
+use std::io;
+use std::path::Path;
+
+use super::image;
+use super::image::Image;
+use super::perlin::Perlin;
+use super::vector::Vec3;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Vec3;
+}
+
+pub struct SolidColor {
+    pub color: Vec3,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+impl From<Vec3> for Box<dyn Texture> {
+    fn from(color: Vec3) -> Self {
+        Box::new(SolidColor { color })
+    }
+}
+
+// Scalar parameters like fuzz are read from the first channel
+impl From<f64> for Box<dyn Texture> {
+    fn from(value: f64) -> Self {
+        Box::new(SolidColor {
+            color: Vec3(value, value, value),
+        })
+    }
+}
+
+// Solid 3D checker pattern, so it doesn't depend on the model's UV layout
+pub struct Checker {
+    pub scale: f64,
+    pub odd: Box<dyn Texture>,
+    pub even: Box<dyn Texture>,
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Vec3) -> Vec3 {
+        let cells = (point.x() / self.scale).floor()
+            + (point.y() / self.scale).floor()
+            + (point.z() / self.scale).floor();
+
+        if cells as i64 % 2 == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+// Plain Perlin noise remapped to [0, 1]
+pub struct Noise {
+    pub perlin: Perlin,
+    pub scale: f64,
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Vec3 {
+        let value = 0.5 * (1.0 + self.perlin.noise(self.scale * point));
+        Vec3(value, value, value)
+    }
+}
+
+pub struct Turbulence {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub depth: usize,
+}
+
+impl Texture for Turbulence {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Vec3 {
+        let value = self.perlin.turbulence(self.scale * point, self.depth);
+        Vec3(value, value, value)
+    }
+}
+
+// Sine bands along z, with the phase pushed around by turbulence to make veins
+pub struct Marble {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub depth: usize,
+    pub color: Vec3,
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, point: Vec3) -> Vec3 {
+        let phase = self.scale * point.z() + 10.0 * self.perlin.turbulence(point, self.depth);
+        0.5 * (1.0 + phase.sin()) * self.color
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    // Maps a texel coordinate that may fall outside the image back into 0..size
+    fn apply(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => num::clamp(i, 0, size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i >= size {
+                    2 * size - 1 - i
+                } else {
+                    i
+                }
+            }
+        };
+
+        wrapped as usize
+    }
+}
+
+// Linear RGB image stored top row first, looked up by UV with v pointing up
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+    pub filter: Filter,
+    pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels,
+            filter: Filter::Bilinear,
+            wrap: WrapMode::Repeat,
+        }
+    }
+
+    // Pass srgb for color maps, leave it off for data like roughness or normals
+    pub fn load_png(path: &Path, srgb: bool) -> io::Result<ImageTexture> {
+        Ok(ImageTexture::new(image::load_png(path, srgb)?))
+    }
+
+    pub fn load_hdr(path: &Path) -> io::Result<ImageTexture> {
+        Ok(ImageTexture::new(image::load_hdr(path)?))
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Vec3 {
+        let i = self.wrap.apply(i, self.width);
+        let j = self.wrap.apply(j, self.height);
+
+        self.pixels[j * self.width + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Vec3) -> Vec3 {
+        if self.pixels.is_empty() {
+            // Debugging aid for missing images
+            return Vec3(0.0, 1.0, 1.0);
+        }
+
+        // Continuous texel coordinates, flipped so v = 0 is the bottom row
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // Texel centers sit on half-integer coordinates
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let i = x.floor();
+                let j = y.floor();
+                let s = x - i;
+                let t = y - j;
+                let (i, j) = (i as i64, j as i64);
+
+                (1.0 - t) * ((1.0 - s) * self.texel(i, j) + s * self.texel(i + 1, j))
+                    + t * ((1.0 - s) * self.texel(i, j + 1) + s * self.texel(i + 1, j + 1))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> ImageTexture {
+        ImageTexture {
+            width: 2,
+            height: 1,
+            pixels: vec![Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0)],
+            filter: Filter::Bilinear,
+            wrap: WrapMode::Clamp,
+        }
+    }
+
+    #[test]
+    fn test_wrap_repeat() {
+        assert_eq!(WrapMode::Repeat.apply(5, 4), 1);
+        assert_eq!(WrapMode::Repeat.apply(-1, 4), 3);
+    }
+
+    #[test]
+    fn test_wrap_clamp() {
+        assert_eq!(WrapMode::Clamp.apply(5, 4), 3);
+        assert_eq!(WrapMode::Clamp.apply(-1, 4), 0);
+    }
+
+    #[test]
+    fn test_wrap_mirror() {
+        assert_eq!(WrapMode::Mirror.apply(4, 4), 3);
+        assert_eq!(WrapMode::Mirror.apply(-1, 4), 0);
+        assert_eq!(WrapMode::Mirror.apply(9, 4), 1);
+    }
+
+    #[test]
+    fn test_bilinear_midpoint() {
+        let texture = gradient();
+        assert_eq!(
+            texture.value(0.5, 0.5, Vec3(0.0, 0.0, 0.0)),
+            Vec3(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_nearest() {
+        let texture = ImageTexture {
+            filter: Filter::Nearest,
+            ..gradient()
+        };
+        assert_eq!(
+            texture.value(0.7, 0.5, Vec3(0.0, 0.0, 0.0)),
+            Vec3(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(image::srgb_to_linear(0.0), 0.0);
+        assert!((image::srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        assert!((image::srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,147 @@
+use num::Complex;
+
+use super::spectrum::{wavelength_to_rgb, WAVELENGTH_MAX, WAVELENGTH_MIN};
+use super::vector::Vec3;
+
+// Wavelengths used when folding the film's reflectance spectrum down to RGB
+const SPECTRAL_SAMPLES: usize = 16;
+
+// What the film is sitting on
+#[derive(Clone, Copy, Debug)]
+pub enum Substrate {
+    Dielectric(f64),
+    // Conductors only have an RGB albedo here, so they're modelled as a mirror with that
+    // reflectance per channel and the phase flip of a perfect conductor
+    Conductor(Vec3),
+}
+
+// Thin transparent coating whose reflections interfere, like a soap bubble or an oil slick
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    // Nanometers
+    pub thickness: f64,
+    pub refractive_index: f64,
+}
+
+// Fresnel amplitude coefficients for s and p polarized light going from medium i to medium j
+fn amplitudes(
+    n_i: Complex<f64>,
+    n_j: Complex<f64>,
+    cos_i: Complex<f64>,
+    cos_j: Complex<f64>,
+) -> (Complex<f64>, Complex<f64>) {
+    (
+        (n_i * cos_i - n_j * cos_j) / (n_i * cos_i + n_j * cos_j),
+        (n_j * cos_i - n_i * cos_j) / (n_j * cos_i + n_i * cos_j),
+    )
+}
+
+// Cosine of the refracted angle by Snell's law, going complex past the critical angle
+fn refracted_cos(n_i: f64, n_j: f64, cos_i: f64) -> Complex<f64> {
+    let sin_sqr = (n_i / n_j).powi(2) * (1.0 - cos_i * cos_i);
+    Complex::new(1.0 - sin_sqr, 0.0).sqrt()
+}
+
+impl ThinFilm {
+    // Reflectance of air-side light at one wavelength, by summing the film's internal
+    // reflections (the Airy formula) and averaging both polarizations. `channel` picks which
+    // albedo channel a conductor substrate uses.
+    pub fn reflectance(
+        &self,
+        cos_theta: f64,
+        outer_index: f64,
+        substrate: Substrate,
+        channel: usize,
+        wavelength: f64,
+    ) -> f64 {
+        let cos_1 = Complex::new(cos_theta.abs().min(1.0), 0.0);
+        let n_1 = Complex::new(outer_index, 0.0);
+        let n_2 = Complex::new(self.refractive_index, 0.0);
+        let cos_2 = refracted_cos(outer_index, self.refractive_index, cos_1.re);
+
+        let (r12_s, r12_p) = amplitudes(n_1, n_2, cos_1, cos_2);
+        let (r23_s, r23_p) = match substrate {
+            Substrate::Dielectric(index) => {
+                let cos_3 = refracted_cos(outer_index, index, cos_1.re);
+                amplitudes(n_2, Complex::new(index, 0.0), cos_2, cos_3)
+            }
+            Substrate::Conductor(albedo) => {
+                let albedo: [f64; 3] = albedo.into();
+                let r = Complex::new(-albedo[channel].max(0.0).sqrt(), 0.0);
+                (r, r)
+            }
+        };
+
+        // Phase difference between successive internal reflections
+        let delta = 4.0 * std::f64::consts::PI * self.refractive_index * self.thickness * cos_2
+            / wavelength;
+        let shift = (Complex::<f64>::i() * delta).exp();
+
+        let total = |r12: Complex<f64>, r23: Complex<f64>| {
+            let r = (r12 + r23 * shift) / (Complex::new(1.0, 0.0) + r12 * r23 * shift);
+            r.norm_sqr()
+        };
+
+        num::clamp(0.5 * (total(r12_s, r23_s) + total(r12_p, r23_p)), 0.0, 1.0)
+    }
+
+    // Reflectance integrated over the visible spectrum into RGB
+    pub fn reflectance_rgb(&self, cos_theta: f64, outer_index: f64, substrate: Substrate) -> Vec3 {
+        let mut sum = [0.0; 3];
+        let mut weight_sum = [0.0; 3];
+
+        for i in 0..SPECTRAL_SAMPLES {
+            let u = (i as f64 + 0.5) / SPECTRAL_SAMPLES as f64;
+            let wavelength = WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+            let weight: [f64; 3] = wavelength_to_rgb(wavelength).into();
+
+            for channel in 0..3 {
+                sum[channel] += weight[channel]
+                    * self.reflectance(cos_theta, outer_index, substrate, channel, wavelength);
+                weight_sum[channel] += weight[channel];
+            }
+        }
+
+        // Normalizing by the weights keeps a flat spectrum exactly flat with this few samples
+        Vec3(
+            num::clamp(sum[0] / weight_sum[0], 0.0, 1.0),
+            num::clamp(sum[1] / weight_sum[1], 0.0, 1.0),
+            num::clamp(sum[2] / weight_sum[2], 0.0, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::fresnel_dielectric;
+
+    #[test]
+    fn test_zero_thickness_is_bare_fresnel() {
+        let film = ThinFilm {
+            thickness: 0.0,
+            refractive_index: 1.33,
+        };
+
+        for cos_theta in [1.0, 0.7, 0.3].iter() {
+            let r = film.reflectance(*cos_theta, 1.0, Substrate::Dielectric(1.5), 0, 550.0);
+            assert!((r - fresnel_dielectric(*cos_theta, 1.0 / 1.5)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_quarter_wave_coating() {
+        // The ideal single layer anti-reflection coating for glass, tuned for green
+        let index = 1.5f64.sqrt();
+        let film = ThinFilm {
+            thickness: 550.0 / (4.0 * index),
+            refractive_index: index,
+        };
+        let reflectance =
+            |wavelength| film.reflectance(1.0, 1.0, Substrate::Dielectric(1.5), 0, wavelength);
+
+        assert!(reflectance(550.0) < 1e-9);
+        assert!(reflectance(550.0) < fresnel_dielectric(1.0, 1.0 / 1.5));
+        assert!(reflectance(420.0) > reflectance(550.0));
+    }
+}
@@ -9,6 +9,9 @@ use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 use std::mem;
 
+use super::random;
+use super::spectrum::Wavelength;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 #[repr(simd)]
 pub struct Vec3(pub f64, pub f64, pub f64);
@@ -49,7 +52,7 @@ impl Vec3 {
     }
 
     pub fn random(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
 
         Vec3(
             rng.gen_range(min, max),
@@ -59,7 +62,7 @@ impl Vec3 {
     }
 
     pub fn random_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
 
         loop {
             let p = Vec3(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0);
@@ -79,7 +82,7 @@ impl Vec3 {
     }
 
     pub fn random_unit_vector() -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
 
         let a = rng.gen_range(0.0, 2.0 * std::f64::consts::PI);
         let z = rng.gen_range(-1.0f64, 1.0f64);
@@ -208,6 +211,9 @@ impl fmt::Display for Vec3 {
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub wavelength: Wavelength,
+    // Moment within the shutter interval the ray was sent out at, for moving models
+    pub time: f64,
 }
 
 impl Ray {
@@ -222,6 +228,308 @@ impl From<Vec3> for [f64; 3] {
     }
 }
 
+// Affine transform as a row major 4x4 matrix, applied to column vectors
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mat4(pub [[f64; 4]; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(factors: Vec3) -> Mat4 {
+        Mat4([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counterclockwise rotation around an axis through the origin, looking down the axis
+    pub fn rotation(axis: Vec3, degrees: f64) -> Mat4 {
+        let Vec3(x, y, z) = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+
+        Mat4([
+            [
+                cos + x * x * k,
+                x * y * k - z * sin,
+                x * z * k + y * sin,
+                0.0,
+            ],
+            [
+                y * x * k + z * sin,
+                cos + y * y * k,
+                y * z * k - x * sin,
+                0.0,
+            ],
+            [
+                z * x * k - y * sin,
+                z * y * k + x * sin,
+                cos + z * z * k,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+
+        Mat4(result)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut left = self.0;
+        let mut right = Mat4::IDENTITY.0;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| left[a][column].abs().total_cmp(&left[b][column].abs()))
+                .unwrap();
+            // NaN sorts above everything, so a matrix with one in it ends up here too
+            let magnitude = left[pivot][column].abs();
+            if magnitude.is_nan() || magnitude < 1e-12 {
+                return None;
+            }
+
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+
+            let scale = 1.0 / left[column][column];
+            for j in 0..4 {
+                left[column][j] *= scale;
+                right[column][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+
+                let factor = left[row][column];
+                for j in 0..4 {
+                    left[row][j] -= factor * left[column][j];
+                    right[row][j] -= factor * right[column][j];
+                }
+            }
+        }
+
+        Some(Mat4(right))
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + Vec3(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+
+        Mat4(result)
+    }
+}
+
+// Position in space, which unlike a direction is moved by translations
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Point3(pub Vec3);
+
+impl ops::Add<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn add(self, rhs: Vec3) -> Point3 {
+        Point3(self.0 + rhs)
+    }
+}
+
+impl ops::Sub for Point3 {
+    type Output = Vec3;
+
+    fn sub(self, rhs: Point3) -> Vec3 {
+        self.0 - rhs.0
+    }
+}
+
+impl ops::Mul<Point3> for Mat4 {
+    type Output = Point3;
+
+    fn mul(self, rhs: Point3) -> Point3 {
+        Point3(self.transform_point(rhs.0))
+    }
+}
+
+// Surface normal. Normals transform as row vectors, so they multiply a matrix from the left, and
+// multiplying by the inverse of a transform applies its inverse transpose. That keeps them
+// perpendicular to surfaces that have been scaled unevenly.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Normal3(pub Vec3);
+
+impl Normal3 {
+    pub fn unit(&self) -> Normal3 {
+        Normal3(self.0.unit())
+    }
+}
+
+impl ops::Mul<Mat4> for Normal3 {
+    type Output = Normal3;
+
+    fn mul(self, rhs: Mat4) -> Normal3 {
+        Normal3(rhs.transpose().transform_vector(self.0))
+    }
+}
+
+// Rotation as a unit quaternion, w + xi + yj + zk with the imaginary part in v
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quat {
+    pub w: f64,
+    pub v: Vec3,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        w: 1.0,
+        v: Vec3(0.0, 0.0, 0.0),
+    };
+
+    // Same convention as Mat4::rotation
+    pub fn rotation(axis: Vec3, degrees: f64) -> Quat {
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Quat {
+            w: cos,
+            v: sin * axis.unit(),
+        }
+    }
+
+    pub fn dot(a: Quat, b: Quat) -> f64 {
+        a.w * b.w + Vec3::dot(a.v, b.v)
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    pub fn unit(&self) -> Quat {
+        let len = Quat::dot(*self, *self).sqrt();
+        Quat {
+            w: self.w / len,
+            v: self.v / len,
+        }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        (*self * Quat { w: 0.0, v } * self.conjugate()).v
+    }
+
+    // Spherical interpolation at constant angular speed, taking the shorter way around
+    pub fn slerp(a: Quat, b: Quat, t: f64) -> Quat {
+        let mut cos = Quat::dot(a, b);
+        let b = if cos < 0.0 {
+            cos = -cos;
+            Quat { w: -b.w, v: -b.v }
+        } else {
+            b
+        };
+
+        // Nearly the same rotation, where the angle is too small to divide by
+        let (s, t) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Quat {
+            w: s * a.w + t * b.w,
+            v: s * a.v + t * b.v,
+        }
+        .unit()
+    }
+}
+
+impl ops::Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat {
+            w: self.w * rhs.w - Vec3::dot(self.v, rhs.v),
+            v: self.w * rhs.v + rhs.w * self.v + Vec3::cross(self.v, rhs.v),
+        }
+    }
+}
+
+impl From<Quat> for Mat4 {
+    fn from(q: Quat) -> Mat4 {
+        let Quat {
+            w,
+            v: Vec3(x, y, z),
+        } = q.unit();
+
+        Mat4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -278,4 +586,70 @@ mod tests {
     fn test_unit() {
         assert_eq!(VEC1.unit(), Vec3(1.0 / 14.0_f64.sqrt(), 2.0 / 14.0_f64.sqrt(), 3.0 / 14.0_f64.sqrt()));
     }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_mat4_rotation() {
+        let rotation = Mat4::rotation(Vec3(0.0, 1.0, 0.0), 90.0);
+        assert_close(
+            rotation.transform_vector(Vec3(1.0, 0.0, 0.0)),
+            Vec3(0.0, 0.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let m =
+            Mat4::translation(VEC1) * Mat4::rotation(VEC2, 30.0) * Mat4::scale(Vec3(2.0, 0.5, 3.0));
+        let inverse = m.inverse().unwrap();
+
+        assert_close(inverse.transform_point(m.transform_point(VEC2)), VEC2);
+        assert_close(m.transform_point(Vec3(0.0, 0.0, 0.0)), VEC1);
+        assert!(Mat4::scale(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Mat4::scale(Vec3(1.0, f64::NAN, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_point_vector_normal_transforms() {
+        let m = Mat4::translation(VEC1) * Mat4::scale(Vec3(2.0, 1.0, 1.0));
+
+        // Only points are translated
+        assert_close((m * Point3(VEC2)).0, Vec3(7.0, 4.0, 4.0));
+        assert_close(m.transform_vector(VEC2), Vec3(6.0, 2.0, 1.0));
+
+        // The normal of the plane x = y stays perpendicular to it after stretching along x
+        let normal = Normal3(Vec3(1.0, -1.0, 0.0)) * m.inverse().unwrap();
+        let along_plane = m.transform_vector(Vec3(1.0, 1.0, 0.0));
+        assert!(Vec3::dot(normal.0, along_plane).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_quat_rotation() {
+        let axis = Vec3(1.0, 2.0, -1.0);
+        let q = Quat::rotation(axis, 70.0);
+        let m = Mat4::rotation(axis, 70.0);
+
+        assert_close(q.rotate(VEC2), m.transform_vector(VEC2));
+        assert_close(
+            Mat4::from(q).transform_vector(VEC2),
+            m.transform_vector(VEC2),
+        );
+        assert_close((q * q.conjugate()).v, Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_quat_slerp() {
+        let a = Quat::IDENTITY;
+        let b = Quat::rotation(Vec3(0.0, 0.0, 1.0), 90.0);
+        let half = Quat::slerp(a, b, 0.5);
+
+        assert_close(
+            half.rotate(Vec3(1.0, 0.0, 0.0)),
+            Quat::rotation(Vec3(0.0, 0.0, 1.0), 45.0).rotate(Vec3(1.0, 0.0, 0.0)),
+        );
+        assert_eq!(Quat::slerp(a, b, 0.0), a);
+    }
 }
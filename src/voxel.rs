@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use super::image::invalid_data;
+use super::vector::Vec3;
+
+const MAGIC: &[u8; 4] = b"VOXG";
+
+// Dense grid of densities covering the unit cube, with x varying fastest and then y
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub data: Vec<f32>,
+}
+
+impl VoxelGrid {
+    // Samples a function of the position in the unit cube at each voxel center
+    pub fn from_fn<F: Fn(Vec3) -> f64>(nx: usize, ny: usize, nz: usize, f: F) -> VoxelGrid {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let point = Vec3(
+                        (i as f64 + 0.5) / nx as f64,
+                        (j as f64 + 0.5) / ny as f64,
+                        (k as f64 + 0.5) / nz as f64,
+                    );
+                    data.push(f(point) as f32);
+                }
+            }
+        }
+
+        VoxelGrid { nx, ny, nz, data }
+    }
+
+    // Raw dense format: the bytes "VOXG", the x, y and z resolutions as little endian u32s, then
+    // every voxel as a little endian f32 in the same order as data
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<VoxelGrid> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a voxel grid"));
+        }
+
+        let mut dimensions = [0; 3];
+        for dimension in dimensions.iter_mut() {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            *dimension = u32::from_le_bytes(bytes) as usize;
+        }
+        let [nx, ny, nz] = dimensions;
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(invalid_data("empty voxel grid"));
+        }
+
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|count| count.checked_mul(nz))
+            .ok_or_else(|| invalid_data("voxel grid too large"))?;
+
+        // Grown as the voxels arrive rather than sized from the header, so a corrupt header fails
+        // with an error at the end of the stream instead of a huge allocation
+        let mut data = Vec::new();
+        for _ in 0..count {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            data.push(f32::from_le_bytes(bytes));
+        }
+
+        Ok(VoxelGrid { nx, ny, nz, data })
+    }
+
+    pub fn load(path: &Path) -> io::Result<VoxelGrid> {
+        VoxelGrid::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.data
+            .iter()
+            .fold(0.0, |max, &value| max.max(value as f64))
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f64 {
+        self.data[(k * self.ny + j) * self.nx + i] as f64
+    }
+
+    // Trilinearly interpolated density at a point in the unit cube, zero outside it
+    pub fn density(&self, point: Vec3) -> f64 {
+        let inside = |value: f64| (0.0..=1.0).contains(&value);
+        if !inside(point.x()) || !inside(point.y()) || !inside(point.z()) {
+            return 0.0;
+        }
+
+        // Lower voxel and weight of the upper one along an axis, measured between voxel centers
+        let axis = |value: f64, size: usize| {
+            let x = num::clamp(value * size as f64 - 0.5, 0.0, (size - 1) as f64);
+            let lower = x.floor() as usize;
+            (lower, (lower + 1).min(size - 1), x - lower as f64)
+        };
+
+        let (i0, i1, u) = axis(point.x(), self.nx);
+        let (j0, j1, v) = axis(point.y(), self.ny);
+        let (k0, k1, w) = axis(point.z(), self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+        let plane = |k: usize| {
+            lerp(
+                lerp(self.voxel(i0, j0, k), self.voxel(i1, j0, k), u),
+                lerp(self.voxel(i0, j1, k), self.voxel(i1, j1, k), u),
+                v,
+            )
+        };
+
+        lerp(plane(k0), plane(k1), w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut bytes = MAGIC.to_vec();
+        for dimension in [2u32, 1, 1].iter() {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        for value in [1.0f32, 3.0].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let grid = VoxelGrid::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!((grid.nx, grid.ny, grid.nz), (2, 1, 1));
+        assert_eq!(grid.max_density(), 3.0);
+
+        // Halfway between the voxel centers, and clamped past them
+        assert!((grid.density(Vec3(0.5, 0.5, 0.5)) - 2.0).abs() < 1e-9);
+        assert!((grid.density(Vec3(0.1, 0.5, 0.5)) - 1.0).abs() < 1e-9);
+        assert_eq!(grid.density(Vec3(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let mut bytes = MAGIC.to_vec();
+        for dimension in [65536u32, 65536, 65536].iter() {
+            bytes.extend_from_slice(&dimension.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+
+        assert!(VoxelGrid::read(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_read_bad_magic() {
+        let bytes = b"VDB0\x01\x00\x00\x00";
+        assert!(VoxelGrid::read(&mut &bytes[..]).is_err());
+    }
+}